@@ -1,12 +1,692 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_channel::{Receiver, Sender};
+
 use super::source;
-use crate::Result;
+use crate::{Error, OutputDevice, ReplayGainMode, Result};
+
+/// The maximum volume, in percent. Matches mpv's default `volume-max`.
+pub const MAX_VOLUME: i64 = 130;
+
+/// Messages sent from the [Controller] to the player thread
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    /// Start playing the `usize`-th entry of the playlist
+    Play(usize),
+    Pause(bool),
+    Seek(Seek),
+    Volume(i64),
+    Mute(bool),
+    Repeat(RepeatMode),
+    /// Go back to A whenever B is reached, or stop looping
+    AbLoop(Option<(f64, f64)>),
+    ReplayGain(ReplayGainMode),
+    /// Play the audio on another device, even if something is playing already
+    Device(OutputDevice),
+    /// Stop playing and close the audio output
+    Stop,
+}
+
+/// Things that happened in the player thread, so whoever is showing its state doesn't need to poll
+/// for changes
+#[derive(Debug)]
+pub enum Event {
+    /// Another track started playing, or the title of the current one changed
+    TrackChanged,
+    PauseChanged(bool),
+    /// A seek finished, so the playback position jumped
+    Seeked,
+    /// A track finished playing
+    EndOfFile,
+    /// The volume changed, or the player was muted or unmuted
+    VolumeChanged,
+    /// Entries were added to the playlist, or removed or moved. Sent by the [Controller] itself.
+    PlaylistChanged,
+    /// Something went wrong, like a file that couldn't be decoded
+    Error(Error),
+}
+
+/// What the player does when a track ends
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Play the next entry of the playlist, and stop after the last one
+    #[default]
+    Off,
+    /// Play the same track again
+    One,
+    /// Play the next entry of the playlist, and go back to the first one after the last
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Seek {
+    /// Seek `f64` seconds forward (or backward, if negative)
+    Relative(f64),
+    /// Seek to the `f64`-th second of the track
+    Absolute(f64),
+}
+
+/// A part of a track, read from the cue points of the media, like the cuesheet of a FLAC file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    /// Where the chapter starts, in seconds
+    pub time: f64,
+}
+
+/// An entry of the player's internal playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: String,
+    pub title: String,
+    /// Where the entry starts the first time it's played, in seconds
+    pub start: Option<f64>,
+    /// The part of the file the entry plays every time: where it starts and where it ends, in
+    /// seconds, like the tracks of a CUE sheet, which share one file
+    pub section: Option<(f64, Option<f64>)>,
+}
+
+impl PlaylistEntry {
+    pub fn new(path: &str) -> Self {
+        let title = path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(path)
+            .to_string();
+        Self {
+            path: path.to_string(),
+            title,
+            start: None,
+            section: None,
+        }
+    }
+}
+
+/// State shared between the [Controller] and the player thread.
+///
+/// The controller writes the settings (playlist, pause, volume...) and the player thread reports
+/// its progress (position, duration, title...).
+#[derive(Debug)]
+pub(crate) struct State {
+    pub playlist: Vec<PlaylistEntry>,
+    /// Index of the playlist entry being played
+    pub position: Option<usize>,
+    pub paused: bool,
+    pub repeat: RepeatMode,
+    pub volume: i64,
+    pub muted: bool,
+    pub replaygain: ReplayGainMode,
+    /// Playback position of the current track, in seconds
+    pub time_pos: f64,
+    /// Duration of the current track, in seconds, if known
+    pub duration: Option<f64>,
+    pub chapters: Vec<Chapter>,
+    /// The A and B points of the A/B loop, in seconds
+    pub ab_loop: (Option<f64>, Option<f64>),
+    pub device: OutputDevice,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            playlist: Vec::new(),
+            position: None,
+            paused: false,
+            repeat: RepeatMode::Off,
+            volume: 100,
+            muted: false,
+            replaygain: ReplayGainMode::Off,
+            time_pos: 0.0,
+            duration: None,
+            chapters: Vec::new(),
+            ab_loop: (None, None),
+            device: OutputDevice::Default,
+        }
+    }
+}
+
+/// Handle to the player thread. Commands are sent through a channel and the playback state is
+/// read from memory shared with the thread.
+#[derive(Debug)]
+pub struct Controller {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    /// The sender of `events`, for the events that don't come from the player thread
+    event_sender: Sender<Event>,
+    state: Arc<Mutex<State>>,
+}
 
-#[derive(Debug, Default)]
-pub struct Controller {}
+impl Default for Controller {
+    fn default() -> Self {
+        let (commands, receiver) = crossbeam_channel::unbounded();
+        let (event_sender, events) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(State::default()));
+        source::start_player_thread(receiver, event_sender.clone(), state.clone());
+        Self {
+            commands,
+            events,
+            event_sender,
+            state,
+        }
+    }
+}
 
 impl Controller {
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands.send(command).map_err(|_| Error::Disconnected)
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Sends [Event::PlaylistChanged]. Must be called after the state is unlocked, so whoever
+    /// receives it sees the new playlist.
+    fn playlist_changed(&self) {
+        self.event_sender.send(Event::PlaylistChanged).ok();
+    }
+
+    /// The [Event]s sent by the player thread. Every event is received only once, even if the
+    /// receiver is cloned.
+    pub fn events(&self) -> Receiver<Event> {
+        self.events.clone()
+    }
+
+    /// Replaces the playlist with `path` and starts playing it
     pub fn play(&mut self, path: &str) -> Result<()> {
-        source::start_player_thread(path);
+        self.play_entry(PlaylistEntry::new(path))
+    }
+
+    /// Like [Controller::play], but only plays `path` from `start` to `end`, in seconds
+    pub fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.play_entry(PlaylistEntry {
+            section: Some((start, end)),
+            ..PlaylistEntry::new(path)
+        })
+    }
+
+    fn play_entry(&mut self, entry: PlaylistEntry) -> Result<()> {
+        {
+            let mut state = self.state();
+            state.playlist = vec![entry];
+            state.position = Some(0);
+        }
+        self.playlist_changed();
+        self.send(Command::Play(0))
+    }
+
+    /// Appends `path` to the playlist. Starts playing it if nothing else is playing.
+    pub fn queue(&mut self, path: &str) -> Result<()> {
+        self.queue_entry(PlaylistEntry::new(path))
+    }
+
+    /// Like [Controller::queue], but the first time `path` is played, it starts `seconds` into it
+    pub fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()> {
+        self.queue_entry(PlaylistEntry {
+            start: Some(seconds),
+            ..PlaylistEntry::new(path)
+        })
+    }
+
+    /// Like [Controller::queue], but only plays `path` from `start` to `end`, in seconds
+    pub fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.queue_entry(PlaylistEntry {
+            section: Some((start, end)),
+            ..PlaylistEntry::new(path)
+        })
+    }
+
+    /// Like [Controller::queue_section], but the first time `path` is played, it starts `seconds`
+    /// into the file instead of at `start`
+    pub fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()> {
+        self.queue_entry(PlaylistEntry {
+            start: Some(seconds),
+            section: Some((start, end)),
+            ..PlaylistEntry::new(path)
+        })
+    }
+
+    fn queue_entry(&mut self, entry: PlaylistEntry) -> Result<()> {
+        let index = {
+            let mut state = self.state();
+            state.playlist.push(entry);
+            match state.position {
+                Some(_) => None,
+                None => {
+                    let index = state.playlist.len() - 1;
+                    state.position = Some(index);
+                    Some(index)
+                }
+            }
+        };
+        self.playlist_changed();
+        match index {
+            Some(index) => self.send(Command::Play(index)),
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts `path` right after the entry being played. Starts playing it if nothing else is
+    /// playing.
+    pub fn queue_next(&mut self, path: &str) -> Result<()> {
+        let inserted = {
+            let mut state = self.state();
+            if let Some(i) = state.position {
+                state.playlist.insert(i + 1, PlaylistEntry::new(path));
+            }
+            state.position.is_some()
+        };
+        if !inserted {
+            return self.queue(path);
+        }
+        self.playlist_changed();
+        Ok(())
+    }
+
+    pub fn play_index(&mut self, index: usize) -> Result<()> {
+        {
+            let mut state = self.state();
+            if index >= state.playlist.len() {
+                return Err(Error::NoPlaylistEntry);
+            }
+            state.position = Some(index);
+        }
+        self.send(Command::Play(index))
+    }
+
+    /// Plays the next entry of the playlist. With [RepeatMode::All], the first entry comes after
+    /// the last one.
+    pub fn playlist_next(&mut self) -> Result<()> {
+        let index = {
+            let state = self.state();
+            match state.position {
+                Some(i) if state.repeat == RepeatMode::All && i + 1 == state.playlist.len() => 0,
+                Some(i) => i + 1,
+                None => 0,
+            }
+        };
+        self.play_index(index)
+    }
+
+    /// Plays the previous entry of the playlist. With [RepeatMode::All], the last entry comes
+    /// before the first one.
+    pub fn playlist_previous(&mut self) -> Result<()> {
+        let index = {
+            let state = self.state();
+            match state.position {
+                Some(i) if i > 0 => i - 1,
+                Some(_) if state.repeat == RepeatMode::All => state.playlist.len() - 1,
+                _ => return Err(Error::NoPlaylistEntry),
+            }
+        };
+        self.play_index(index)
+    }
+
+    /// Removes the `index`-th entry of the playlist. If it's the one being played, the next one
+    /// starts playing.
+    pub fn playlist_remove(&mut self, index: usize) -> Result<()> {
+        let command = {
+            let mut state = self.state();
+            if index >= state.playlist.len() {
+                return Err(Error::NoPlaylistEntry);
+            }
+            state.playlist.remove(index);
+
+            match state.position {
+                Some(i) if i > index => {
+                    state.position = Some(i - 1);
+                    None
+                }
+                Some(i) if i == index && index < state.playlist.len() => Some(Command::Play(index)),
+                Some(i) if i == index => {
+                    state.position = None;
+                    Some(Command::Stop)
+                }
+                _ => None,
+            }
+        };
+        self.playlist_changed();
+        match command {
+            Some(command) => self.send(command),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes every entry of the playlist, except the one being played
+    pub fn playlist_clear(&mut self) {
+        {
+            let mut state = self.state();
+            match state.position {
+                Some(i) => {
+                    let entry = state.playlist.swap_remove(i);
+                    state.playlist = vec![entry];
+                    state.position = Some(0);
+                }
+                None => state.playlist.clear(),
+            }
+        }
+        self.playlist_changed();
+    }
+
+    pub fn stop(&mut self) -> Result<()> {
+        self.state().position = None;
+        self.send(Command::Stop)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.state().paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.state().paused = paused;
+        self.send(Command::Pause(paused))
+    }
+
+    pub fn toggle_pause(&mut self) -> Result<()> {
+        let paused = self.paused();
+        self.set_paused(!paused)
+    }
+
+    /// Seeks `seconds` forward, or backward if `seconds` is negative
+    pub fn seek(&mut self, seconds: f64) -> Result<()> {
+        self.send(Command::Seek(Seek::Relative(seconds)))
+    }
+
+    /// Seeks to the `seconds`-th second of the current track
+    pub fn seek_absolute(&mut self, seconds: f64) -> Result<()> {
+        self.send(Command::Seek(Seek::Absolute(seconds.max(0.0))))
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.state().repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) -> Result<()> {
+        self.state().repeat = repeat;
+        self.send(Command::Repeat(repeat))
+    }
+
+    /// Volume, in percent. Goes from 0 to [MAX_VOLUME].
+    pub fn volume(&self) -> i64 {
+        self.state().volume
+    }
+
+    pub fn set_volume(&mut self, volume: i64) -> Result<()> {
+        let volume = volume.clamp(0, MAX_VOLUME);
+        self.state().volume = volume;
+        self.send(Command::Volume(volume))
+    }
+
+    pub fn muted(&self) -> bool {
+        self.state().muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) -> Result<()> {
+        self.state().muted = muted;
+        self.send(Command::Mute(muted))
+    }
+
+    pub fn replaygain(&self) -> ReplayGainMode {
+        self.state().replaygain
+    }
+
+    /// Normalizes the loudness of the tracks with their ReplayGain tags. Tracks without the tags
+    /// are played as they are.
+    pub fn set_replaygain(&mut self, mode: ReplayGainMode) -> Result<()> {
+        self.state().replaygain = mode;
+        self.send(Command::ReplayGain(mode))
+    }
+
+    pub fn output_device(&self) -> OutputDevice {
+        self.state().device.clone()
+    }
+
+    /// Switches the audio output to `device`. The audio that was buffered for the previous device
+    /// is lost, which is a fraction of a second.
+    pub fn set_output_device(&mut self, device: OutputDevice) -> Result<()> {
+        self.state().device = device.clone();
+        self.send(Command::Device(device))
+    }
+
+    /// Playback position of the current track, in seconds
+    pub fn time_pos(&self) -> f64 {
+        self.state().time_pos
+    }
+
+    /// Duration of the current track, in seconds, if it's known
+    pub fn duration(&self) -> Option<f64> {
+        self.state().duration
+    }
+
+    /// Chapters of the current track, in order
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.state().chapters.clone()
+    }
+
+    /// Index of the chapter being played, if one started already
+    pub fn chapter(&self) -> Option<usize> {
+        let state = self.state();
+        state
+            .chapters
+            .iter()
+            .rposition(|chapter| chapter.time <= state.time_pos)
+    }
+
+    /// Seeks to the start of the `index`-th chapter
+    pub fn set_chapter(&mut self, index: usize) -> Result<()> {
+        let time = self
+            .state()
+            .chapters
+            .get(index)
+            .ok_or(Error::NoChapter)?
+            .time;
+        self.seek_absolute(time)
+    }
+
+    pub fn ab_loop(&self) -> (Option<f64>, Option<f64>) {
+        self.state().ab_loop
+    }
+
+    /// Sets the A and B points of the A/B loop, in seconds. The player loops once both are set.
+    pub fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()> {
+        self.state().ab_loop = (a, b);
+        self.send(Command::AbLoop(a.zip(b)))
+    }
+
+    /// Title of the current track
+    pub fn media_title(&self) -> Option<String> {
+        let state = self.state();
+        state
+            .position
+            .and_then(|i| state.playlist.get(i))
+            .map(|entry| entry.title.clone())
+    }
+
+    pub fn playlist_len(&self) -> usize {
+        self.state().playlist.len()
+    }
+
+    pub fn playlist_title(&self, index: usize) -> Option<String> {
+        self.state()
+            .playlist
+            .get(index)
+            .map(|entry| entry.title.clone())
+    }
+
+    pub fn playlist_path(&self, index: usize) -> Option<String> {
+        self.state()
+            .playlist
+            .get(index)
+            .map(|entry| entry.path.clone())
+    }
+
+    /// The part of the file the `index`-th entry plays, if it's only a section of it
+    pub fn playlist_section(&self, index: usize) -> Option<(f64, Option<f64>)> {
+        self.state().playlist.get(index)?.section
+    }
+
+    /// Index of the playlist entry being played
+    pub fn playlist_position(&self) -> Option<usize> {
+        self.state().position
+    }
+
+    /// Moves the `from`-th entry of the playlist so it becomes the `to`-th one. The current track
+    /// keeps playing.
+    pub fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        {
+            let mut state = self.state();
+            if from >= state.playlist.len() || to >= state.playlist.len() {
+                return Err(Error::NoPlaylistEntry);
+            }
+
+            let entry = state.playlist.remove(from);
+            state.playlist.insert(to, entry);
+            state.position = state.position.map(|i| match i {
+                i if i == from => to,
+                i if from < i && i <= to => i - 1,
+                i if to <= i && i < from => i + 1,
+                i => i,
+            });
+        }
+        self.playlist_changed();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A controller without a player thread, so the commands it sends can be checked
+    fn controller(entries: usize, position: Option<usize>) -> (Controller, Receiver<Command>) {
+        let (commands, receiver) = crossbeam_channel::unbounded();
+        let (event_sender, events) = crossbeam_channel::unbounded();
+        let state = State {
+            playlist: (0..entries)
+                .map(|i| PlaylistEntry::new(&format!("/music/{}.mp3", i)))
+                .collect(),
+            position,
+            ..Default::default()
+        };
+        let controller = Controller {
+            commands,
+            events,
+            event_sender,
+            state: Arc::new(Mutex::new(state)),
+        };
+        (controller, receiver)
+    }
+
+    /// How many [Event::PlaylistChanged] were sent since the last call
+    fn playlist_changes(controller: &Controller) -> usize {
+        controller
+            .events
+            .try_iter()
+            .filter(|event| matches!(event, Event::PlaylistChanged))
+            .count()
+    }
+
+    fn titles(controller: &Controller) -> Vec<String> {
+        (0..controller.playlist_len())
+            .filter_map(|i| controller.playlist_title(i))
+            .collect()
+    }
+
+    #[test]
+    fn test_playlist_move() {
+        let (mut c, _commands) = controller(5, Some(2));
+
+        // The entry being played is moved
+        c.playlist_move(2, 4).unwrap();
+        assert_eq!(titles(&c), ["0.mp3", "1.mp3", "3.mp3", "4.mp3", "2.mp3"]);
+        assert_eq!(c.playlist_position(), Some(4));
+
+        // An entry is moved from before the current one to after it, and back
+        c.playlist_move(0, 4).unwrap();
+        assert_eq!(c.playlist_position(), Some(3));
+        c.playlist_move(4, 0).unwrap();
+        assert_eq!(c.playlist_position(), Some(4));
+        assert_eq!(titles(&c), ["0.mp3", "1.mp3", "3.mp3", "4.mp3", "2.mp3"]);
+
+        // Entries on the same side of the current one don't change its position
+        c.playlist_move(0, 3).unwrap();
+        assert_eq!(c.playlist_position(), Some(4));
+
+        assert!(matches!(c.playlist_move(5, 0), Err(Error::NoPlaylistEntry)));
+        assert!(matches!(c.playlist_move(0, 5), Err(Error::NoPlaylistEntry)));
+        assert_eq!(playlist_changes(&c), 4);
+    }
+
+    #[test]
+    fn test_playlist_remove() {
+        let (mut c, commands) = controller(4, Some(2));
+
+        c.playlist_remove(0).unwrap();
+        assert_eq!(titles(&c), ["1.mp3", "2.mp3", "3.mp3"]);
+        assert_eq!(c.playlist_position(), Some(1));
+        c.playlist_remove(2).unwrap();
+        assert_eq!(c.playlist_position(), Some(1));
+        assert!(commands.try_recv().is_err());
+        assert_eq!(playlist_changes(&c), 2);
+
+        // Removing the entry being played plays the next one, or stops after the last
+        c.playlist_remove(1).unwrap();
+        assert_eq!(titles(&c), ["1.mp3"]);
+        assert_eq!(commands.try_recv(), Ok(Command::Stop));
+        assert_eq!(c.playlist_position(), None);
+
+        let (mut c, commands) = controller(3, Some(1));
+        c.playlist_remove(1).unwrap();
+        assert_eq!(commands.try_recv(), Ok(Command::Play(1)));
+        assert_eq!(c.playlist_position(), Some(1));
+        assert_eq!(c.media_title().as_deref(), Some("2.mp3"));
+
+        assert!(matches!(c.playlist_remove(2), Err(Error::NoPlaylistEntry)));
+        assert_eq!(playlist_changes(&c), 1);
+    }
+
+    #[test]
+    fn test_queue() {
+        let (mut c, commands) = controller(0, None);
+
+        // Queueing into an empty playlist starts playing
+        c.queue("/music/0.mp3").unwrap();
+        assert_eq!(commands.try_recv(), Ok(Command::Play(0)));
+        c.queue("/music/1.mp3").unwrap();
+        c.queue_next("/music/2.mp3").unwrap();
+        assert!(commands.try_recv().is_err());
+        assert_eq!(titles(&c), ["0.mp3", "2.mp3", "1.mp3"]);
+        assert_eq!(playlist_changes(&c), 3);
+
+        c.playlist_clear();
+        assert_eq!(titles(&c), ["0.mp3"]);
+        assert_eq!(playlist_changes(&c), 1);
+    }
+
+    #[test]
+    fn test_playlist_next_and_previous() {
+        let (mut c, commands) = controller(3, Some(2));
+        assert!(matches!(c.playlist_next(), Err(Error::NoPlaylistEntry)));
+
+        // With RepeatMode::All, the playlist wraps around in both directions
+        c.state().repeat = RepeatMode::All;
+        c.playlist_next().unwrap();
+        assert_eq!(commands.try_recv(), Ok(Command::Play(0)));
+        assert_eq!(c.playlist_position(), Some(0));
+        c.playlist_previous().unwrap();
+        assert_eq!(commands.try_recv(), Ok(Command::Play(2)));
+
+        c.state().repeat = RepeatMode::Off;
+        c.play_index(0).unwrap();
+        assert!(matches!(c.playlist_previous(), Err(Error::NoPlaylistEntry)));
+        c.playlist_next().unwrap();
+        assert_eq!(c.playlist_position(), Some(1));
+    }
+}
@@ -0,0 +1,57 @@
+use std::{error, fmt, io};
+
+use symphonia::core::errors::Error as SymError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The media file couldn't be read
+    Io(io::Error),
+    /// There's no demuxer or codec for the media
+    UnsupportedFormat(String),
+    /// The media has no track that can be decoded
+    NoAudioTrack,
+    /// The decoder failed and the track can't be played anymore
+    Decoder(SymError),
+    /// The audio output device couldn't be opened or written to
+    OutputDevice(String),
+    /// yt-dlp or ffmpeg couldn't be executed or exited with an error
+    External(String),
+    /// There's no entry at the requested position of the playlist
+    NoPlaylistEntry,
+    /// The current track has no chapter at the requested position
+    NoChapter,
+    /// The player thread has stopped
+    Disconnected,
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decoder(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO error: {}", e),
+            Error::UnsupportedFormat(s) => write!(f, "Unsupported format: {}", s),
+            Error::NoAudioTrack => write!(f, "No supported audio track"),
+            Error::Decoder(e) => write!(f, "Decoder error: {}", e),
+            Error::OutputDevice(s) => write!(f, "Audio output error: {}", s),
+            Error::External(s) => write!(f, "{}", s),
+            Error::NoPlaylistEntry => write!(f, "No such song in the playlist"),
+            Error::NoChapter => write!(f, "No such chapter"),
+            Error::Disconnected => write!(f, "The player thread has stopped"),
+        }
+    }
+}
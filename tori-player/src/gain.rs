@@ -0,0 +1,252 @@
+//! Everything that changes the loudness of the audio between the decoder and the output device:
+//! the volume, ReplayGain and the short fades that avoid clicks when pausing or skipping.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time,
+};
+
+use symphonia::core::{
+    conv::{ConvertibleSample, FromSample, IntoSample},
+    meta::{MetadataRevision, StandardTagKey},
+};
+
+/// How long it takes to fade the audio in or out
+pub const FADE: time::Duration = time::Duration::from_millis(25);
+
+/// Which ReplayGain values are used to normalize the loudness of the tracks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    /// Parses the same values as mpv's `replaygain` option: "no", "track" or "album"
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "no" | "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ReplayGainMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "no"),
+            Self::Track => write!(f, "track"),
+            Self::Album => write!(f, "album"),
+        }
+    }
+}
+
+/// The ReplayGain tags of a track. Gains are in dB, peaks are linear.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Reads the ReplayGain tags of the metadata, if there's any
+    pub fn from_metadata(metadata: &MetadataRevision) -> Option<Self> {
+        let tag = |key| {
+            metadata
+                .tags()
+                .iter()
+                .find(|tag| tag.std_key == Some(key))
+                .and_then(|tag| parse_tag_value(&tag.value.to_string()))
+        };
+
+        let replaygain = Self {
+            track_gain: tag(StandardTagKey::ReplayGainTrackGain),
+            track_peak: tag(StandardTagKey::ReplayGainTrackPeak),
+            album_gain: tag(StandardTagKey::ReplayGainAlbumGain),
+            album_peak: tag(StandardTagKey::ReplayGainAlbumPeak),
+        };
+        (replaygain != Self::default()).then_some(replaygain)
+    }
+
+    /// The factor samples should be multiplied by. Falls back to the track values in album mode
+    /// and vice-versa, and never amplifies the peak above full scale.
+    pub fn factor(&self, mode: ReplayGainMode) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => (
+                self.track_gain.or(self.album_gain),
+                self.track_peak.or(self.album_peak),
+            ),
+            ReplayGainMode::Album => (
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            ),
+        };
+
+        let factor = gain.map_or(1.0, |db| 10f32.powf(db / 20.0));
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parses values like "-6.54 dB" or "0.988553"
+fn parse_tag_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok()
+}
+
+/// Fades the audio in and out. It's applied by the output stream callback, after the ring buffer,
+/// so the samples that are already buffered can be faded too.
+pub struct Fade {
+    /// Whether the audio should be heard. Set by the player thread through a [FadeHandle].
+    audible: Arc<AtomicBool>,
+    /// Current gain of the fade, from 0.0 to 1.0
+    level: f32,
+    /// How much the level changes each frame
+    step: f32,
+}
+
+/// Starts fading the audio of a [Fade] in or out
+#[derive(Clone)]
+pub struct FadeHandle(Arc<AtomicBool>);
+
+impl Fade {
+    pub fn new(sample_rate: u32) -> (Self, FadeHandle) {
+        let audible = Arc::new(AtomicBool::new(true));
+        let fade = Self {
+            audible: audible.clone(),
+            level: 1.0,
+            step: 1.0 / (FADE.as_secs_f32() * sample_rate as f32),
+        };
+        (fade, FadeHandle(audible))
+    }
+
+    /// Whether the audio is fading out, or already faded out
+    pub fn is_fading_out(&self) -> bool {
+        !self.audible.load(Ordering::Acquire)
+    }
+
+    /// Whether the audio faded out completely, so the samples aren't heard at all
+    pub fn is_silent(&self) -> bool {
+        self.level == 0.0
+    }
+
+    /// Applies the fade to interleaved samples
+    pub fn apply<T>(&mut self, samples: &mut [T], channels: usize)
+    where
+        T: ConvertibleSample + IntoSample<f32>,
+    {
+        let target = if self.audible.load(Ordering::Acquire) {
+            1.0
+        } else {
+            0.0
+        };
+        if self.level == target && target == 1.0 {
+            return;
+        }
+
+        for frame in samples.chunks_mut(channels.max(1)) {
+            self.level = if self.level < target {
+                (self.level + self.step).min(target)
+            } else {
+                (self.level - self.step).max(target)
+            };
+            for s in frame {
+                let x: f32 = (*s).into_sample();
+                *s = <T as FromSample<f32>>::from_sample(x * self.level);
+            }
+        }
+    }
+}
+
+impl FadeHandle {
+    pub fn fade_in(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn fade_out(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag_value() {
+        assert_eq!(parse_tag_value("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_tag_value(" +3.00 db "), Some(3.0));
+        assert_eq!(parse_tag_value("0.988553"), Some(0.988553));
+        assert_eq!(parse_tag_value("loud"), None);
+    }
+
+    #[test]
+    fn test_factor() {
+        let replaygain = ReplayGain {
+            track_gain: Some(-6.0206),
+            track_peak: Some(0.5),
+            album_gain: Some(6.0206),
+            album_peak: Some(0.8),
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+
+        assert_eq!(replaygain.factor(ReplayGainMode::Off), 1.0);
+        assert!(close(replaygain.factor(ReplayGainMode::Track), 0.5));
+        // +6 dB would double the peak above full scale, so it's limited
+        assert!(close(replaygain.factor(ReplayGainMode::Album), 1.25));
+
+        // Each mode falls back to the other's values
+        let track_only = ReplayGain {
+            track_gain: Some(-6.0206),
+            ..Default::default()
+        };
+        assert!(close(track_only.factor(ReplayGainMode::Album), 0.5));
+        let album_only = ReplayGain {
+            album_gain: Some(-6.0206),
+            ..Default::default()
+        };
+        assert!(close(album_only.factor(ReplayGainMode::Track), 0.5));
+
+        assert_eq!(ReplayGain::default().factor(ReplayGainMode::Track), 1.0);
+    }
+
+    #[test]
+    fn test_fade() {
+        // 25 frames at 1 kHz
+        let (mut fade, handle) = Fade::new(1000);
+        let mut samples = [1.0f32; 40];
+        fade.apply(&mut samples, 1);
+        assert_eq!(samples, [1.0; 40]);
+
+        handle.fade_out();
+        assert!(fade.is_fading_out() && !fade.is_silent());
+        fade.apply(&mut samples, 1);
+        assert!(samples[0] < 1.0 && samples[0] > samples[10]);
+        assert_eq!(samples[24..], [0.0; 16]);
+        assert!(fade.is_silent());
+
+        handle.fade_in();
+        let mut samples = [1.0f32; 40];
+        fade.apply(&mut samples, 1);
+        assert!(!fade.is_fading_out() && !fade.is_silent());
+        assert!(samples[0] > 0.0 && samples[0] < samples[10]);
+        assert_eq!(samples[25..], [1.0; 15]);
+    }
+}
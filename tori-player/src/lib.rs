@@ -1,11 +1,18 @@
 pub mod controller;
+pub mod error;
+mod gain;
+mod opus;
 mod output;
 mod resampler;
+mod sinks;
 pub mod source;
 
 use controller::Controller;
+pub use error::Error;
+pub use gain::ReplayGainMode;
+pub use output::{list_devices, OutputDevice};
 
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 pub struct Player {
     pub controller: Controller,
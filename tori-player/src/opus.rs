@@ -0,0 +1,286 @@
+//! Symphonia can demux WebM and Matroska, but it can't decode Opus, which is what most streams from
+//! YouTube use. This module wraps ffmpeg's Opus decoder in a symphonia [Decoder], so Opus tracks
+//! are decoded in-process, at their original quality.
+
+use std::sync::OnceLock;
+
+use ac_ffmpeg::codec::{audio::AudioDecoder, audio::AudioFrame, Decoder as _};
+use ac_ffmpeg::packet::PacketMut;
+use log::warn;
+use symphonia::core::{
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal, SignalSpec},
+    codecs::{
+        CodecDescriptor, CodecParameters, CodecRegistry, Decoder, DecoderOptions, FinalizeResult,
+        CODEC_TYPE_OPUS,
+    },
+    errors::{Error, Result},
+    formats::Packet,
+    support_codec,
+};
+
+/// Opus is always decoded at 48 kHz
+const SAMPLE_RATE: u32 = 48000;
+
+/// An Opus packet holds at most 120ms of audio
+const MAX_FRAMES_PER_PACKET: u64 = 5760;
+
+/// How many seconds before the position of a seek the decoding starts, dropping that audio. The
+/// decoder needs that much to converge after it's reset, it's what the Opus spec recommends.
+const PRE_ROLL: f64 = 0.08;
+
+/// How many seconds of audio to decode and drop before the position of a seek, for decoders that
+/// need to be primed after they're reset
+pub fn pre_roll(params: &CodecParameters) -> f64 {
+    if params.codec == CODEC_TYPE_OPUS {
+        PRE_ROLL
+    } else {
+        0.0
+    }
+}
+
+/// Symphonia's codecs, plus the ones implemented by tori-player
+pub fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        registry.register_all::<OpusDecoder>();
+        registry
+    })
+}
+
+pub struct OpusDecoder {
+    params: CodecParameters,
+    decoder: AudioDecoder,
+    buf: AudioBuffer<f32>,
+    pre_skip: PreSkip,
+}
+
+impl OpusDecoder {
+    fn open_ffmpeg_decoder(params: &CodecParameters) -> Result<AudioDecoder> {
+        AudioDecoder::builder("opus")
+            .and_then(|builder| {
+                builder
+                    .extradata(params.extra_data.as_deref())
+                    // Some versions of ffmpeg drop the pre-skip themselves and some don't, so
+                    // it's always done by [OpusDecoder::decode]
+                    .set_option("flags2", "+skip_manual")
+                    .build()
+            })
+            .map_err(|e| {
+                warn!("failed to open ffmpeg's opus decoder: {}", e);
+                Error::Unsupported("opus: ffmpeg has no opus decoder")
+            })
+    }
+
+    /// Appends the samples of a decoded frame to the buffer
+    fn append(&mut self, frame: &AudioFrame) -> Result<()> {
+        let channels = self.buf.spec().channels.count();
+        if frame.channel_layout().channels() as usize != channels {
+            return Err(Error::DecodeError("opus: the channel count changed"));
+        }
+
+        let start = self.buf.frames();
+        let frames = frame.samples();
+        if start + frames > self.buf.capacity() {
+            return Err(Error::DecodeError("opus: packet is too long"));
+        }
+        self.buf.render_reserved(Some(frames));
+
+        let planes = frame.planes();
+        let sample = |bytes: &[u8]| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match frame.sample_format().name() {
+            // ffmpeg's own decoder outputs planar samples
+            "fltp" => {
+                for (ch, plane) in planes.iter().enumerate().take(channels) {
+                    let dst = &mut self.buf.chan_mut(ch)[start..start + frames];
+                    for (d, s) in dst.iter_mut().zip(plane.data().chunks_exact(4)) {
+                        *d = sample(s);
+                    }
+                }
+            }
+            // libopus outputs interleaved samples
+            "flt" => {
+                let src = planes[0].data().chunks_exact(4).take(frames * channels);
+                for (i, s) in src.enumerate() {
+                    self.buf.chan_mut(i % channels)[start + i / channels] = sample(s);
+                }
+            }
+            _ => return Err(Error::Unsupported("opus: unexpected sample format")),
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+        // The channel count is in the OpusHead, which is the codec's extra data
+        let channel_count = params
+            .channels
+            .map(|channels| channels.count())
+            .or_else(|| params.extra_data.as_ref()?.get(9).map(|&n| n as usize))
+            .unwrap_or(2);
+        let channels = Channels::from_bits_truncate((1 << channel_count) - 1);
+
+        let decoder = Self::open_ffmpeg_decoder(params)?;
+        let buf = AudioBuffer::new(
+            MAX_FRAMES_PER_PACKET,
+            SignalSpec::new(SAMPLE_RATE, channels),
+        );
+
+        Ok(Self {
+            params: params.clone(),
+            decoder,
+            buf,
+            pre_skip: PreSkip::new(pre_skip(params)),
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus (ffmpeg)")]
+    }
+
+    fn reset(&mut self) {
+        // ac-ffmpeg can't flush a decoder without ending the stream, so a new one is opened
+        if let Ok(decoder) = Self::open_ffmpeg_decoder(&self.params) {
+            self.decoder = decoder;
+        }
+        // It's primed by the packets before the seeked position instead, see [pre_roll]
+        self.pre_skip.reset();
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        self.buf.clear();
+
+        let ffmpeg_packet = PacketMut::from(packet.buf()).freeze();
+        self.decoder.try_push(ffmpeg_packet).map_err(|e| {
+            warn!("failed to decode an opus packet: {}", e);
+            Error::DecodeError("opus: invalid packet")
+        })?;
+
+        loop {
+            match self.decoder.take() {
+                Ok(Some(frame)) => self.append(&frame)?,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("failed to decode an opus packet: {}", e);
+                    return Err(Error::DecodeError("opus: invalid packet"));
+                }
+            }
+        }
+
+        let skipped = self.pre_skip.trim(packet.ts(), self.buf.frames());
+        if skipped > 0 {
+            self.buf.shift(skipped);
+        }
+
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        Default::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
+
+/// How many frames at the start of the stream only prime the decoder and aren't part of the
+/// audio. It's the pre-skip of the OpusHead, which is the codec's extra data.
+fn pre_skip(params: &CodecParameters) -> usize {
+    let from_head = || {
+        let head = params.extra_data.as_deref()?;
+        let bytes = head.strip_prefix(b"OpusHead")?.get(2..4)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]) as u32)
+    };
+    params.delay.or_else(from_head).unwrap_or(0) as usize
+}
+
+/// Drops the frames at the start of the stream that only prime the decoder. They're only dropped
+/// at the start of the stream: after a seek, the packets before the seeked position prime it
+/// instead and they're dropped by their timestamps, so dropping more would shift the audio.
+#[derive(Debug)]
+struct PreSkip {
+    frames: usize,
+    /// Frames that still have to be dropped
+    left: usize,
+}
+
+impl PreSkip {
+    fn new(frames: usize) -> Self {
+        Self {
+            frames,
+            left: frames,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.left = 0;
+    }
+
+    /// How many frames to drop from the start of a packet at `ts` that decoded to `frames`
+    fn trim(&mut self, ts: u64, frames: usize) -> usize {
+        if ts == 0 {
+            self.left = self.frames;
+        }
+        let trimmed = self.left.min(frames);
+        self.left -= trimmed;
+        trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pre_skip() {
+        // Version 1, 2 channels, a pre-skip of 312 frames, 48 kHz, no gain and mapping family 0
+        let head = b"OpusHead\x01\x02\x38\x01\x80\xbb\x00\x00\x00\x00\x00";
+        let mut params = CodecParameters::new();
+        assert_eq!(pre_skip(&params), 0);
+
+        params.with_extra_data(Box::from(&head[..]));
+        assert_eq!(pre_skip(&params), 312);
+
+        // The delay of the container wins, Ogg sets it from the same header
+        params.with_delay(120);
+        assert_eq!(pre_skip(&params), 120);
+
+        params.delay = None;
+        params.with_extra_data(Box::from(&b"OpusHead"[..]));
+        assert_eq!(pre_skip(&params), 0);
+    }
+
+    #[test]
+    fn test_pre_skip_after_seek() {
+        let mut pre_skip = PreSkip::new(312);
+        assert_eq!(pre_skip.trim(0, 960), 312);
+        assert_eq!(pre_skip.trim(960, 960), 0);
+
+        // A seek resets the decoder, and the audio after it isn't trimmed again
+        pre_skip.reset();
+        assert_eq!(pre_skip.trim(48000, 960), 0);
+        assert_eq!(pre_skip.trim(48960, 120), 0);
+
+        // Unless it goes back to the start
+        pre_skip.reset();
+        assert_eq!(pre_skip.trim(0, 120), 120);
+        assert_eq!(pre_skip.trim(120, 960), 192);
+        assert_eq!(pre_skip.trim(1080, 960), 0);
+    }
+
+    #[test]
+    fn test_pre_roll() {
+        let mut params = CodecParameters::new();
+        assert_eq!(pre_roll(&params), 0.0);
+        params.for_codec(CODEC_TYPE_OPUS);
+        assert_eq!(pre_roll(&params), PRE_ROLL);
+    }
+}
@@ -8,11 +8,17 @@
 
 //! Platform-dependant Audio Outputs
 
-use std::{result, time};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::{fmt, io, path::PathBuf, result, thread, time};
 
+use super::gain::{Fade, FadeHandle, FADE};
 use super::resampler::Resampler;
+use super::sinks::{NullAudioOutput, WavFileOutput};
 use symphonia::core::audio::{AudioBufferRef, RawSample, SampleBuffer, SignalSpec};
-use symphonia::core::conv::{ConvertibleSample, IntoSample};
+use symphonia::core::conv::{ConvertibleSample, FromSample, IntoSample};
 use symphonia::core::units::Duration;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
@@ -22,7 +28,16 @@ use log::{error, info};
 
 pub trait AudioOutput {
     fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
+    /// Plays every sample that was written and pauses the stream
     fn flush(&mut self);
+    /// Discards every sample that was written but not played yet
+    fn clear(&mut self);
+    /// Sets the factor every sample is multiplied by. 1.0 leaves the audio untouched.
+    fn set_gain(&mut self, gain: f32);
+    /// Stops playing, but keeps the samples that weren't played yet, so [resume](Self::resume)
+    /// continues exactly where the audio stopped
+    fn pause(&mut self);
+    fn resume(&mut self);
 }
 
 #[allow(dead_code)]
@@ -32,10 +47,136 @@ pub enum AudioOutputError {
     OpenStreamError,
     PlayStreamError,
     StreamClosedError,
+    DeviceNotFoundError(String),
+    FileError(io::Error),
+}
+
+impl fmt::Display for AudioOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioOutputError::OpenStreamError => write!(f, "failed to open the audio stream"),
+            AudioOutputError::PlayStreamError => write!(f, "failed to play the audio stream"),
+            AudioOutputError::StreamClosedError => write!(f, "the audio stream was closed"),
+            AudioOutputError::DeviceNotFoundError(name) => {
+                write!(f, "there's no audio device named '{}'", name)
+            }
+            AudioOutputError::FileError(e) => write!(f, "failed to write the audio file: {}", e),
+        }
+    }
+}
+
+impl From<AudioOutputError> for crate::Error {
+    fn from(e: AudioOutputError) -> Self {
+        crate::Error::OutputDevice(e.to_string())
+    }
 }
 
 pub type Result<T> = result::Result<T, AudioOutputError>;
 
+/// Where the audio is played
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OutputDevice {
+    /// The default device of the default host
+    #[default]
+    Default,
+    /// A device of some host, like ALSA or JACK. Uses the default host if `host` is None.
+    Cpal { host: Option<String>, name: String },
+    /// Discards the audio, at the same pace a real device would play it
+    Null,
+    /// Writes the audio to a WAV file, as fast as it's decoded
+    File(PathBuf),
+}
+
+impl OutputDevice {
+    /// Parses a device in the format it's displayed: `default`, `null`, `file:<path>`,
+    /// `<host>:<device>` or just `<device>`.
+    pub fn parse(s: &str) -> Self {
+        if s == "default" {
+            return Self::Default;
+        }
+        if s == "null" {
+            return Self::Null;
+        }
+        if let Some(path) = s.strip_prefix("file:") {
+            return Self::File(PathBuf::from(path));
+        }
+
+        // Device names may have colons too, like ALSA's "hw:CARD=PCH,DEV=0"
+        if let Some((host, name)) = s.split_once(':') {
+            let is_host = cpal::available_hosts()
+                .iter()
+                .any(|id| id.name().eq_ignore_ascii_case(host));
+            if is_host {
+                return Self::Cpal {
+                    host: Some(host.to_string()),
+                    name: name.to_string(),
+                };
+            }
+        }
+
+        Self::Cpal {
+            host: None,
+            name: s.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for OutputDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputDevice::Default => write!(f, "default"),
+            OutputDevice::Cpal {
+                host: Some(host),
+                name,
+            } => write!(f, "{}:{}", host, name),
+            OutputDevice::Cpal { host: None, name } => write!(f, "{}", name),
+            OutputDevice::Null => write!(f, "null"),
+            OutputDevice::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Lists the output devices of every host compiled in, plus the default and null devices
+pub fn list_devices() -> Vec<OutputDevice> {
+    let mut devices = vec![OutputDevice::Default];
+
+    for id in cpal::available_hosts() {
+        let Ok(host) = cpal::host_from_id(id) else {
+            continue;
+        };
+        let Ok(host_devices) = host.output_devices() else {
+            continue;
+        };
+        devices.extend(
+            host_devices
+                .filter_map(|device| device.name().ok())
+                .map(|name| OutputDevice::Cpal {
+                    host: Some(id.name().to_string()),
+                    name,
+                }),
+        );
+    }
+
+    devices.push(OutputDevice::Null);
+    devices
+}
+
+/// Opens an audio output on `device`
+pub fn try_open(
+    spec: SignalSpec,
+    duration: Duration,
+    device: &OutputDevice,
+) -> Result<Box<dyn AudioOutput>> {
+    match device {
+        OutputDevice::Null => Ok(Box::new(NullAudioOutput::new())),
+        OutputDevice::File(path) => Ok(Box::new(WavFileOutput::create(path, spec, duration)?)),
+        OutputDevice::Default => CpalAudioOutput::try_open(spec, duration, None),
+        OutputDevice::Cpal { host, name } => {
+            CpalAudioOutput::try_open(spec, duration, Some((host.as_deref(), name)))
+        }
+    }
+}
+
 pub struct CpalAudioOutput;
 
 trait AudioOutputSample:
@@ -48,17 +189,21 @@ impl AudioOutputSample for i16 {}
 impl AudioOutputSample for u16 {}
 
 impl CpalAudioOutput {
-    pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-        // Get default host.
-        let host = cpal::default_host();
-
-        // Get the default audio output device.
-        let device = match host.default_output_device() {
-            Some(device) => device,
-            _ => {
-                error!("failed to get default audio output device");
-                return Err(AudioOutputError::OpenStreamError);
-            }
+    /// Opens the device named `name` of the `host`, or the default device if `device` is None
+    pub fn try_open(
+        spec: SignalSpec,
+        duration: Duration,
+        device: Option<(Option<&str>, &str)>,
+    ) -> Result<Box<dyn AudioOutput>> {
+        let device = match device {
+            Some((host, name)) => find_device(host, name)?,
+            None => match cpal::default_host().default_output_device() {
+                Some(device) => device,
+                _ => {
+                    error!("failed to get default audio output device");
+                    return Err(AudioOutputError::OpenStreamError);
+                }
+            },
         };
 
         let config = match device.default_output_config() {
@@ -71,108 +216,144 @@ impl CpalAudioOutput {
 
         // Select proper playback routine based on sample format.
         match config.sample_format() {
-            cpal::SampleFormat::F32 => {
-                CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device)
-            }
-            cpal::SampleFormat::I16 => {
-                CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device)
+            cpal::SampleFormat::F32 => CpalAudioOutputImpl::<f32>::try_open(spec, duration, device),
+            cpal::SampleFormat::I16 => CpalAudioOutputImpl::<i16>::try_open(spec, duration, device),
+            cpal::SampleFormat::U16 => CpalAudioOutputImpl::<u16>::try_open(spec, duration, device),
+            sample_format => {
+                error!("unsupported sample format: {}", sample_format);
+                Err(AudioOutputError::OpenStreamError)
             }
-            cpal::SampleFormat::U16 => {
-                CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device)
-            }
-            _ => unimplemented!(),
         }
     }
 }
 
+/// Finds an output device by its name. Uses the default host if `host` is None.
+fn find_device(host: Option<&str>, name: &str) -> Result<cpal::Device> {
+    let host = match host {
+        Some(host) => cpal::available_hosts()
+            .into_iter()
+            .find(|id| id.name().eq_ignore_ascii_case(host))
+            .and_then(|id| cpal::host_from_id(id).ok())
+            .ok_or_else(|| AudioOutputError::DeviceNotFoundError(format!("{}:{}", host, name)))?,
+        None => cpal::default_host(),
+    };
+
+    host.output_devices()
+        .ok()
+        .and_then(|mut devices| devices.find(|d| d.name().ok().as_deref() == Some(name)))
+        .ok_or_else(|| AudioOutputError::DeviceNotFoundError(name.to_string()))
+}
+
 struct CpalAudioOutputImpl<T: AudioOutputSample>
 where
     T: AudioOutputSample,
 {
+    device: cpal::Device,
+    config: cpal::StreamConfig,
+    ring_buf: SpscRb<T>,
     ring_buf_producer: rb::Producer<T>,
+    spec: SignalSpec,
+    duration: Duration,
     sample_buf: SampleBuffer<T>,
     stream: cpal::Stream,
     resampler: Option<Resampler<T>>,
+    gain: f32,
+    scaled: Vec<T>,
+    /// Set when the ring buffer should be emptied. Only the consumer (the output stream callback)
+    /// can safely do that, so it clears the flag when it's done.
+    clear_requested: Arc<AtomicBool>,
+    /// Fades the audio of the stream in and out
+    fade: FadeHandle,
+    paused: bool,
 }
 
 impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
     pub fn try_open(
         spec: SignalSpec,
         duration: Duration,
-        device: &cpal::Device,
+        device: cpal::Device,
     ) -> Result<Box<dyn AudioOutput>> {
-        let num_channels = spec.channels.count();
-
-        // Output audio stream config.
-        let config = if cfg!(not(target_os = "windows")) {
-            cpal::StreamConfig {
-                channels: num_channels as cpal::ChannelCount,
-                sample_rate: cpal::SampleRate(spec.rate),
-                buffer_size: cpal::BufferSize::Default,
-            }
-        } else {
-            // Use the default config for Windows.
-            device
-                .default_output_config()
-                .expect("Failed to get the default output config.")
-                .config()
-        };
-
-        // Create a ring buffer with a capacity for up-to 200ms of audio.
-        let ring_len = ((200 * config.sample_rate.0 as usize) / 1000) * num_channels;
-
-        let ring_buf = SpscRb::new(ring_len);
-        let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
-
-        let stream_result = device.build_output_stream(
-            &config,
-            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                // Write out as many samples as possible from the ring buffer to the audio
-                // output.
-                let written = ring_buf_consumer.read(data).unwrap_or(0);
-
-                // Mute any remaining samples.
-                data[written..].iter_mut().for_each(|s| *s = T::MID);
-            },
-            move |err| error!("audio output error: {}", err),
-            Some(time::Duration::from_secs(1)),
-        );
-
-        if let Err(err) = stream_result {
-            error!("audio output stream open error: {}", err);
-
-            return Err(AudioOutputError::OpenStreamError);
-        }
-
-        let stream = stream_result.unwrap();
-
-        // Start the output stream.
-        if let Err(err) = stream.play() {
-            error!("audio output stream play error: {}", err);
-
-            return Err(AudioOutputError::PlayStreamError);
-        }
+        let config = stream_config(spec, &device)?;
+        let clear_requested = Arc::new(AtomicBool::new(false));
+        let (ring_buf, ring_buf_producer, stream, fade) =
+            open_stream(&device, &config, clear_requested.clone())?;
 
         let sample_buf = SampleBuffer::<T>::new(duration, spec);
-
-        let resampler = if spec.rate != config.sample_rate.0 {
-            info!("resampling {} Hz to {} Hz", spec.rate, config.sample_rate.0);
-            Some(Resampler::new(
-                spec,
-                config.sample_rate.0 as usize,
-                duration,
-            ))
-        } else {
-            None
-        };
+        let resampler = make_resampler(spec, &config, duration);
 
         Ok(Box::new(CpalAudioOutputImpl {
+            device,
+            config,
+            ring_buf,
             ring_buf_producer,
+            spec,
+            duration,
             sample_buf,
             stream,
             resampler,
+            gain: 1.0,
+            scaled: Vec::new(),
+            clear_requested,
+            fade,
+            paused: false,
         }))
     }
+
+    /// Adapts the output to decoded audio with another spec, or a bigger capacity. This happens
+    /// when a file changes its sample rate or channel count midway, or when the next track of the
+    /// playlist is different from the previous one.
+    fn reconfigure(&mut self, spec: SignalSpec, duration: Duration) -> Result<()> {
+        // The samples left in the resampler have the previous spec
+        self.flush_resampler();
+
+        let config = stream_config(spec, &self.device)?;
+        if config.channels != self.config.channels {
+            // The stream can't take another channel count, so it's reopened after it plays what's
+            // left in the ring buffer.
+            self.drain();
+            let _ = self.stream.pause();
+            let (ring_buf, ring_buf_producer, stream, fade) =
+                open_stream(&self.device, &config, self.clear_requested.clone())?;
+            self.ring_buf = ring_buf;
+            self.ring_buf_producer = ring_buf_producer;
+            self.stream = stream;
+            self.fade = fade;
+            self.config = config;
+        }
+
+        // Otherwise the stream is kept open and the audio is resampled to its sample rate, so
+        // there's no gap.
+        self.resampler = make_resampler(spec, &self.config, duration);
+        self.sample_buf = SampleBuffer::<T>::new(duration, spec);
+        self.spec = spec;
+        self.duration = duration;
+        Ok(())
+    }
+
+    /// Writes the samples that are still in the resampler to the ring buffer
+    fn flush_resampler(&mut self) {
+        if let Some(resampler) = &mut self.resampler {
+            let remaining_samples = resampler.flush().unwrap_or_default();
+            let remaining_samples = apply_gain(remaining_samples, self.gain, &mut self.scaled);
+            write_all(&self.ring_buf_producer, remaining_samples);
+        }
+    }
+
+    /// Waits until the ring buffer is played, which takes as long as the audio in it, so never
+    /// more than one buffer. The wait doesn't go over that when the stream callback isn't being
+    /// called, like while paused.
+    fn drain(&self) {
+        let deadline = time::Instant::now() + playing_time(self.ring_buf.count(), &self.config);
+        while !self.ring_buf.is_empty() && time::Instant::now() < deadline {
+            thread::sleep(time::Duration::from_millis(5));
+        }
+    }
+
+    /// Fades the audio out and waits until the stream callback has played the fade
+    fn fade_out(&self) {
+        self.fade.fade_out();
+        thread::sleep(FADE + time::Duration::from_millis(10));
+    }
 }
 
 impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
@@ -182,7 +363,13 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
             return Ok(());
         }
 
-        let mut samples = if let Some(resampler) = &mut self.resampler {
+        let spec = *decoded.spec();
+        let duration = decoded.capacity() as u64;
+        if spec != self.spec || duration > self.duration {
+            self.reconfigure(spec, duration)?;
+        }
+
+        let samples = if let Some(resampler) = &mut self.resampler {
             // Resampling is required. The resampler will return interleaved samples in the
             // correct sample format.
             match resampler.resample(decoded) {
@@ -196,10 +383,10 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
             self.sample_buf.samples()
         };
 
+        let samples = apply_gain(samples, self.gain, &mut self.scaled);
+
         // Write all samples to the ring buffer.
-        while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
-            samples = &samples[written..];
-        }
+        write_all(&self.ring_buf_producer, samples);
 
         Ok(())
     }
@@ -207,19 +394,259 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
     fn flush(&mut self) {
         // If there is a resampler, then it may need to be flushed
         // depending on the number of samples it has.
+        self.flush_resampler();
+
+        self.drain();
+
+        // Flush is best-effort, ignore the returned result.
+        let _ = self.stream.pause();
+    }
+
+    fn clear(&mut self) {
         if let Some(resampler) = &mut self.resampler {
-            let mut remaining_samples = resampler.flush().unwrap_or_default();
+            resampler.reset();
+        }
+
+        // Cutting the audio mid-wave would click. A paused stream was already faded out.
+        if !self.paused {
+            self.fade_out();
+        }
+
+        // Wait for the stream callback to empty the ring buffer, otherwise the samples we write next
+        // could be discarded too. The timeout covers the case where the callback isn't being called.
+        // A paused stream doesn't call it at all.
+        self.clear_requested.store(true, Ordering::Release);
+        let deadline = time::Instant::now() + time::Duration::from_millis(100);
+        while !self.paused
+            && self.clear_requested.load(Ordering::Acquire)
+            && time::Instant::now() < deadline
+        {
+            thread::sleep(time::Duration::from_millis(1));
+        }
+
+        // The callback isn't running, so we can empty the buffer ourselves
+        if self.clear_requested.swap(false, Ordering::AcqRel) {
+            let _ = self.ring_buf.consumer().skip_pending();
+        }
+
+        if !self.paused {
+            self.fade.fade_in();
+        }
+    }
+
+    fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    fn pause(&mut self) {
+        if self.paused {
+            return;
+        }
+
+        // The ring buffer is left as is, and the stream callback isn't called until it's resumed.
+        // The samples played during the fade are left in it too, so they're played again.
+        self.fade_out();
+        if let Err(err) = self.stream.pause() {
+            error!("audio output stream pause error: {}", err);
+        }
+        self.paused = true;
+    }
 
-            while let Some(written) = self.ring_buf_producer.write_blocking(remaining_samples) {
-                remaining_samples = &remaining_samples[written..];
+    fn resume(&mut self) {
+        self.fade.fade_in();
+        if let Err(err) = self.stream.play() {
+            error!("audio output stream play error: {}", err);
+        }
+        self.paused = false;
+    }
+}
+
+/// The config of the output stream for audio with this spec
+fn stream_config(spec: SignalSpec, device: &cpal::Device) -> Result<cpal::StreamConfig> {
+    if cfg!(not(target_os = "windows")) {
+        Ok(cpal::StreamConfig {
+            channels: spec.channels.count() as cpal::ChannelCount,
+            sample_rate: cpal::SampleRate(spec.rate),
+            buffer_size: cpal::BufferSize::Default,
+        })
+    } else {
+        // Use the default config for Windows.
+        match device.default_output_config() {
+            Ok(config) => Ok(config.config()),
+            Err(err) => {
+                error!("failed to get the default output config: {}", err);
+                Err(AudioOutputError::OpenStreamError)
             }
         }
+    }
+}
 
-        // Flush is best-effort, ignore the returned result.
-        let _ = self.stream.pause();
+/// Opens and starts an output stream that plays whatever is written to the returned ring buffer
+fn open_stream<T: AudioOutputSample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    clear_requested: Arc<AtomicBool>,
+) -> Result<(SpscRb<T>, rb::Producer<T>, cpal::Stream, FadeHandle)> {
+    // Create a ring buffer with a capacity for up-to 200ms of audio.
+    let ring_len = ((200 * config.sample_rate.0 as usize) / 1000) * config.channels as usize;
+
+    let ring_buf = SpscRb::new(ring_len);
+    let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
+    let (mut fade, fade_handle) = Fade::new(config.sample_rate.0);
+    let channels = config.channels as usize;
+
+    // While fading out, the samples are only peeked at, so they're still in the ring buffer when
+    // the audio is resumed and it continues exactly where it was heard to stop, fading in.
+    // `held` is how many samples at the start of the ring buffer were already played that way.
+    let mut peeked = vec![T::MID; ring_len];
+    let mut held = 0;
+
+    let stream_result = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            // Drop stale samples, usually after a seek.
+            if clear_requested.load(Ordering::Acquire) {
+                let _ = ring_buf_consumer.skip_pending();
+                held = 0;
+                clear_requested.store(false, Ordering::Release);
+            }
+
+            // Write out as many samples as possible from the ring buffer to the audio
+            // output.
+            let written = if !fade.is_fading_out() {
+                held = 0;
+                ring_buf_consumer.read(data).unwrap_or(0)
+            } else if fade.is_silent() {
+                0
+            } else {
+                let wanted = (held + data.len()).min(peeked.len());
+                let peeked_len = ring_buf_consumer.get(&mut peeked[..wanted]).unwrap_or(0);
+                let written = peeked_len.saturating_sub(held);
+                data[..written].copy_from_slice(&peeked[held..held + written]);
+                held += written;
+                written
+            };
+            fade.apply(&mut data[..written], channels);
+
+            // Mute any remaining samples.
+            data[written..].iter_mut().for_each(|s| *s = T::MID);
+        },
+        move |err| error!("audio output error: {}", err),
+        Some(time::Duration::from_secs(1)),
+    );
+
+    let stream = match stream_result {
+        Ok(stream) => stream,
+        Err(err) => {
+            error!("audio output stream open error: {}", err);
+            return Err(AudioOutputError::OpenStreamError);
+        }
+    };
+
+    // Start the output stream.
+    if let Err(err) = stream.play() {
+        error!("audio output stream play error: {}", err);
+
+        return Err(AudioOutputError::PlayStreamError);
     }
+
+    Ok((ring_buf, ring_buf_producer, stream, fade_handle))
 }
 
-pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-    CpalAudioOutput::try_open(spec, duration)
+/// How long the stream takes to play `samples` interleaved samples
+fn playing_time(samples: usize, config: &cpal::StreamConfig) -> time::Duration {
+    let frames = samples / config.channels.max(1) as usize;
+    time::Duration::from_secs_f64(frames as f64 / config.sample_rate.0 as f64)
+}
+
+/// A resampler from the spec's sample rate to the stream's, if they're different
+fn make_resampler<T: AudioOutputSample>(
+    spec: SignalSpec,
+    config: &cpal::StreamConfig,
+    duration: Duration,
+) -> Option<Resampler<T>> {
+    if spec.rate != config.sample_rate.0 {
+        info!("resampling {} Hz to {} Hz", spec.rate, config.sample_rate.0);
+        Some(Resampler::new(
+            spec,
+            config.sample_rate.0 as usize,
+            duration,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Multiplies the samples by `gain`, using `scaled` as a buffer if needed
+fn apply_gain<'a, T: AudioOutputSample>(
+    samples: &'a [T],
+    gain: f32,
+    scaled: &'a mut Vec<T>,
+) -> &'a [T] {
+    if gain == 1.0 {
+        return samples;
+    }
+
+    scaled.clear();
+    scaled.extend(samples.iter().map(|&s| {
+        let s: f32 = s.into_sample();
+        <T as FromSample<f32>>::from_sample(s * gain)
+    }));
+    scaled
+}
+
+fn write_all<T: AudioOutputSample>(producer: &rb::Producer<T>, mut samples: &[T]) {
+    while let Some(written) = producer.write_blocking(samples) {
+        samples = &samples[written..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playing_time() {
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(48000),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        // The whole ring buffer, of 200ms
+        assert_eq!(
+            playing_time(2 * 9600, &config),
+            time::Duration::from_millis(200)
+        );
+        assert_eq!(playing_time(0, &config), time::Duration::ZERO);
+    }
+
+    #[test]
+    fn test_output_device_round_trip() {
+        for (s, device) in [
+            ("default", OutputDevice::Default),
+            ("null", OutputDevice::Null),
+            (
+                "file:/tmp/out.wav",
+                OutputDevice::File("/tmp/out.wav".into()),
+            ),
+            (
+                "hw:CARD=PCH,DEV=0",
+                OutputDevice::Cpal {
+                    host: None,
+                    name: "hw:CARD=PCH,DEV=0".into(),
+                },
+            ),
+            #[cfg(target_os = "linux")]
+            (
+                "ALSA:hw:CARD=PCH,DEV=0",
+                OutputDevice::Cpal {
+                    host: Some("ALSA".into()),
+                    name: "hw:CARD=PCH,DEV=0".into(),
+                },
+            ),
+        ] {
+            assert_eq!(OutputDevice::parse(s), device);
+            assert_eq!(device.to_string(), s);
+        }
+    }
 }
@@ -107,6 +107,14 @@ where
         Some(self.resample_inner())
     }
 
+    /// Discards any samples in the resample buffer.
+    pub fn reset(&mut self) {
+        for channel in self.input.iter_mut() {
+            channel.clear();
+        }
+        rubato::Resampler::reset(&mut self.resampler);
+    }
+
     /// Resample any remaining samples in the resample buffer.
     pub fn flush(&mut self) -> Option<&[T]> {
         let len = self.input[0].len();
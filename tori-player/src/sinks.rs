@@ -0,0 +1,300 @@
+//! Audio outputs that don't need an audio device, mostly for testing
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    thread,
+    time::{self, Instant},
+};
+
+use log::error;
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec};
+use symphonia::core::units::Duration;
+
+use crate::output::{AudioOutput, AudioOutputError, Result};
+use crate::resampler::Resampler;
+
+/// How much audio can be written ahead of what's being "played", like the ring buffer of
+/// [CpalAudioOutput](crate::output::CpalAudioOutput)
+const BUFFER: time::Duration = time::Duration::from_millis(200);
+
+/// Discards the audio, but takes as long as a real device would take to play it
+pub struct NullAudioOutput {
+    /// When the audio written so far finishes playing
+    ends_at: Instant,
+    /// How much audio was left to play when the output was paused
+    paused: Option<time::Duration>,
+}
+
+impl NullAudioOutput {
+    pub fn new() -> Self {
+        Self {
+            ends_at: Instant::now(),
+            paused: None,
+        }
+    }
+}
+
+impl AudioOutput for NullAudioOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        let spec = decoded.spec();
+        let length = time::Duration::from_secs_f64(decoded.frames() as f64 / spec.rate as f64);
+
+        // If nothing was written for a while, the device would have run out of samples
+        let now = Instant::now();
+        self.ends_at = self.ends_at.max(now) + length;
+
+        if let Some(ahead) = self.ends_at.checked_duration_since(now + BUFFER) {
+            thread::sleep(ahead);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        if let Some(left) = self.ends_at.checked_duration_since(Instant::now()) {
+            thread::sleep(left);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ends_at = Instant::now();
+        if self.paused.is_some() {
+            self.paused = Some(time::Duration::ZERO);
+        }
+    }
+
+    fn set_gain(&mut self, _gain: f32) {}
+
+    fn pause(&mut self) {
+        if self.paused.is_none() {
+            self.paused = Some(self.ends_at.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(left) = self.paused.take() {
+            self.ends_at = Instant::now() + left;
+        }
+    }
+}
+
+/// Writes the audio to a 32-bit float WAV file. The sample rate and channel count of the file are
+/// the ones of the first track, later tracks are resampled to it.
+pub struct WavFileOutput {
+    file: BufWriter<File>,
+    /// Spec of the file
+    file_spec: SignalSpec,
+    /// Spec and capacity of the audio being written
+    spec: SignalSpec,
+    duration: Duration,
+    sample_buf: SampleBuffer<f32>,
+    resampler: Option<Resampler<f32>>,
+    gain: f32,
+    scaled: Vec<f32>,
+    /// Bytes of audio written so far
+    data_len: u32,
+}
+
+impl WavFileOutput {
+    pub fn create(path: &Path, spec: SignalSpec, duration: Duration) -> Result<Self> {
+        let mut file = BufWriter::new(File::create(path).map_err(AudioOutputError::FileError)?);
+        write_header(&mut file, spec, 0).map_err(AudioOutputError::FileError)?;
+
+        Ok(Self {
+            file,
+            file_spec: spec,
+            spec,
+            duration,
+            sample_buf: SampleBuffer::new(duration, spec),
+            resampler: None,
+            gain: 1.0,
+            scaled: Vec::new(),
+            data_len: 0,
+        })
+    }
+
+    fn reconfigure(&mut self, spec: SignalSpec, duration: Duration) -> Result<()> {
+        if spec.channels.count() != self.file_spec.channels.count() {
+            return Err(AudioOutputError::FileError(io::Error::other(
+                "the channel count changed",
+            )));
+        }
+
+        self.flush_resampler()?;
+        self.resampler = (spec.rate != self.file_spec.rate)
+            .then(|| Resampler::new(spec, self.file_spec.rate as usize, duration));
+        self.sample_buf = SampleBuffer::new(duration, spec);
+        self.spec = spec;
+        self.duration = duration;
+        Ok(())
+    }
+
+    fn flush_resampler(&mut self) -> Result<()> {
+        if let Some(resampler) = &mut self.resampler {
+            if let Some(samples) = resampler.flush() {
+                write_samples(
+                    &mut self.file,
+                    &mut self.data_len,
+                    samples,
+                    self.gain,
+                    &mut self.scaled,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the header with the length of the audio, so the file is valid even if more audio
+    /// is written later
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.file, self.file_spec, self.data_len)?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+}
+
+impl AudioOutput for WavFileOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        if decoded.frames() == 0 {
+            return Ok(());
+        }
+
+        let spec = *decoded.spec();
+        let duration = decoded.capacity() as u64;
+        if spec != self.spec || duration > self.duration {
+            self.reconfigure(spec, duration)?;
+        }
+
+        let samples = if let Some(resampler) = &mut self.resampler {
+            match resampler.resample(decoded) {
+                Some(resampled) => resampled,
+                None => return Ok(()),
+            }
+        } else {
+            self.sample_buf.copy_interleaved_ref(decoded);
+            self.sample_buf.samples()
+        };
+
+        write_samples(
+            &mut self.file,
+            &mut self.data_len,
+            samples,
+            self.gain,
+            &mut self.scaled,
+        )
+    }
+
+    fn flush(&mut self) {
+        let finished = self
+            .flush_resampler()
+            .and_then(|_| self.finish().map_err(AudioOutputError::FileError));
+        if let Err(e) = finished {
+            error!("{}", e);
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.reset();
+        }
+    }
+
+    fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    // A file isn't played, so there's nothing to pause
+    fn pause(&mut self) {}
+    fn resume(&mut self) {}
+}
+
+impl Drop for WavFileOutput {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+fn write_samples(
+    file: &mut BufWriter<File>,
+    data_len: &mut u32,
+    samples: &[f32],
+    gain: f32,
+    scaled: &mut Vec<f32>,
+) -> Result<()> {
+    scaled.clear();
+    scaled.extend(samples.iter().map(|s| s * gain));
+
+    for sample in scaled.iter() {
+        file.write_all(&sample.to_le_bytes())
+            .map_err(AudioOutputError::FileError)?;
+    }
+    *data_len = data_len.saturating_add(4 * scaled.len() as u32);
+    Ok(())
+}
+
+/// Writes the 44 byte header of a WAV file with 32-bit float samples
+fn write_header(w: &mut impl Write, spec: SignalSpec, data_len: u32) -> io::Result<()> {
+    let channels = spec.channels.count() as u16;
+    let block_align = channels * 4;
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&36u32.saturating_add(data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&3u16.to_le_bytes())?; // WAVE_FORMAT_IEEE_FLOAT
+    w.write_all(&channels.to_le_bytes())?;
+    w.write_all(&spec.rate.to_le_bytes())?;
+    w.write_all(&(spec.rate * block_align as u32).to_le_bytes())?;
+    w.write_all(&block_align.to_le_bytes())?;
+    w.write_all(&32u16.to_le_bytes())?;
+
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::{AsAudioBufferRef, AudioBuffer, Channels, Signal};
+
+    use super::*;
+
+    #[test]
+    fn test_wav_header_is_rewritten() {
+        let path = std::env::temp_dir().join(format!("tori-player-{}.wav", std::process::id()));
+        let spec = SignalSpec::new(44100, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+        let mut buffer = AudioBuffer::<f32>::new(100, spec);
+        buffer.render_reserved(Some(100));
+
+        let mut output = WavFileOutput::create(&path, spec, 100).unwrap();
+        output.write(buffer.as_audio_buffer_ref()).unwrap();
+        output.write(buffer.as_audio_buffer_ref()).unwrap();
+        output.flush();
+
+        // 2 writes of 100 frames with 2 channels of 4 bytes
+        let data_len = 2 * 100 * 2 * 4;
+        let file = std::fs::read(&path).unwrap();
+        let u32_at = |i: usize| u32::from_le_bytes(file[i..i + 4].try_into().unwrap());
+        assert_eq!(file.len(), 44 + data_len);
+        assert_eq!(&file[0..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + data_len as u32);
+        assert_eq!(&file[36..40], b"data");
+        assert_eq!(u32_at(40), data_len as u32);
+        assert_eq!(u32_at(24), 44100);
+
+        // Writing more after a flush keeps the file valid
+        output.write(buffer.as_audio_buffer_ref()).unwrap();
+        drop(output);
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file.len(), 44 + data_len + 800);
+        assert_eq!(
+            u32::from_le_bytes(file[40..44].try_into().unwrap()),
+            data_len as u32 + 800
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -1,142 +1,807 @@
-use crate::Result;
+use crate::{
+    controller::{Chapter, Command, Event, RepeatMode, Seek, State},
+    gain::ReplayGain,
+    opus, Error, ReplayGainMode, Result,
+};
 use std::{
+    borrow::Cow,
     fs::File,
     io,
     path::Path,
-    process::{Command, Stdio},
+    process::{self, Stdio},
+    sync::{Arc, Mutex},
     thread,
 };
 
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use log::error;
 use symphonia::core::{
-    codecs::{DecoderOptions, CODEC_TYPE_NULL},
-    errors::Error as SymError,
-    formats::FormatOptions,
+    audio::{AudioBufferRef, Signal},
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::{Error as SymError, SeekErrorKind},
+    formats::{Cue, FormatOptions, FormatReader, SeekMode, SeekTo},
     io::{MediaSource, MediaSourceStream, ReadOnlySource},
-    meta::MetadataOptions,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
     probe::Hint,
+    units::{Time, TimeBase},
 };
 
-use crate::output::CpalAudioOutput;
+use crate::output::{self, AudioOutput, OutputDevice};
 
-// TODO: remove `expects` and `unwraps`
-pub fn start_player_thread(path: &str) {
-    let (mss, hint) = mss_from_path(path).unwrap();
+/// How long before the end of a track we start opening the next one
+const PRELOAD_SECONDS: f64 = 10.0;
 
-    // Use the default options for metadata and format readers.
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts = FormatOptions {
-        enable_gapless: true,
-        ..Default::default()
-    };
+pub(crate) fn start_player_thread(
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    state: Arc<Mutex<State>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || PlayerThread::new(commands, events, state).run())
+}
+
+/// A media file opened for decoding
+struct Source {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    title: Option<String>,
+    replaygain: Option<ReplayGain>,
+    chapters: Vec<Chapter>,
+    /// After a seek, audio before this timestamp is decoded but not played
+    skip_until: Option<u64>,
+}
+
+impl Source {
+    fn open(path: &str) -> Result<Self> {
+        let (mss, hint) = mss_from_path(path)?;
 
-    // Probe the media source.
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .expect("unsupported format");
+        // Use the default options for metadata and format readers.
+        let meta_opts: MetadataOptions = Default::default();
+        let fmt_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+
+        // Probe the media source.
+        let mut probed = symphonia::default::get_probe()
+            .format(&hint, mss, &fmt_opts, &meta_opts)
+            .map_err(|e| match e {
+                SymError::IoError(e) => Error::Io(e),
+                e => Error::UnsupportedFormat(e.to_string()),
+            })?;
+
+        // Get the instantiated format reader.
+        let mut format = probed.format;
 
-    // Get the instantiated format reader.
-    let mut format = probed.format;
+        // Tags from the container, or tags read while probing, like ID3v2
+        let title = format
+            .metadata()
+            .current()
+            .and_then(title_from_metadata)
+            .or_else(|| {
+                probed
+                    .metadata
+                    .get()
+                    .and_then(|m| m.current().and_then(title_from_metadata))
+            });
+        let replaygain = format
+            .metadata()
+            .current()
+            .and_then(ReplayGain::from_metadata)
+            .or_else(|| {
+                probed
+                    .metadata
+                    .get()
+                    .and_then(|m| m.current().and_then(ReplayGain::from_metadata))
+            });
 
-    // Find the first audio track with a known (decodeable) codec.
+        let (decoder, track_id, time_base) = make_decoder(format.as_ref())?;
+        let chapters = match time_base {
+            Some(time_base) => format
+                .cues()
+                .iter()
+                .map(|cue| chapter_from_cue(cue, time_base))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            time_base,
+            title,
+            replaygain,
+            chapters,
+            skip_until: None,
+        })
+    }
+
+    /// Picks a track again and creates a new decoder for it. Needed when the format reader returns
+    /// [SymError::ResetRequired], which happens between the streams of a chained Ogg file.
+    fn reset(&mut self) -> Result<()> {
+        let (decoder, track_id, time_base) = make_decoder(self.format.as_ref())?;
+        self.decoder = decoder;
+        self.track_id = track_id;
+        self.time_base = time_base;
+        self.skip_until = None;
+        Ok(())
+    }
+
+    fn replaygain_factor(&self, mode: ReplayGainMode) -> f32 {
+        self.replaygain
+            .map_or(1.0, |replaygain| replaygain.factor(mode))
+    }
+
+    fn duration(&self) -> Option<f64> {
+        let track = self
+            .format
+            .tracks()
+            .iter()
+            .find(|t| t.id == self.track_id)?;
+        let n_frames = track.codec_params.n_frames?;
+        let time_base = self.time_base?;
+        Some(seconds(time_base.calc_time(n_frames)))
+    }
+}
+
+/// Creates a decoder for the first audio track with a known (decodeable) codec. Returns it along
+/// with the track identifier, used to filter packets, and the track's time base.
+fn make_decoder(format: &dyn FormatReader) -> Result<(Box<dyn Decoder>, u32, Option<TimeBase>)> {
     let track = format
         .tracks()
         .iter()
         .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .expect("no supported audio tracks");
+        .ok_or(Error::NoAudioTrack)?;
 
     // Use the default options for the decoder.
     let dec_opts: DecoderOptions = Default::default();
 
-    // Create a decoder for the track.
-    let mut decoder = symphonia::default::get_codecs()
+    let decoder = opus::codecs()
         .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+        .map_err(|e| Error::UnsupportedFormat(e.to_string()))?;
 
-    // Store the track identifier, it will be used to filter packets.
-    let track_id = track.id;
+    Ok((decoder, track.id, track.codec_params.time_base))
+}
+
+/// The next entry of the playlist, opened in a background thread before the current one ends
+struct Preload {
+    index: usize,
+    path: String,
+    source: Receiver<Result<Source>>,
+}
 
-    // Spawn a thread to read packets from the format reader and send them to the decoder.
-    // TODO: if the user pauses the player, this thread continues to run. Should this really be the
-    // case?
-    thread::spawn(move || {
-        let mut audio_output = None;
+/// Outcome of decoding a single packet
+enum Decoded {
+    /// Some audio was written to the output, and the playback is now at `f64` seconds
+    Played(f64),
+    /// The packet was skipped
+    Skipped,
+    /// The track ended
+    EndOfTrack,
+    /// The track can't be played anymore
+    Failed(Error),
+}
+
+/// Reads packets from the current [Source], decodes them and writes them to the audio output, while
+/// listening for [Command]s from the [Controller](crate::controller::Controller).
+struct PlayerThread {
+    commands: Receiver<Command>,
+    /// Changes in the playback, and errors, which are reported instead of stopping the thread
+    events: Sender<Event>,
+    state: Arc<Mutex<State>>,
+    source: Option<Source>,
+    audio_output: Option<Box<dyn AudioOutput>>,
+    preload: Option<Preload>,
+    paused: bool,
+    repeat: RepeatMode,
+    ab_loop: Option<(f64, f64)>,
+    /// Where the section of the current entry ends, if it's not the end of the file
+    end: Option<f64>,
+    volume: i64,
+    muted: bool,
+    replaygain: ReplayGainMode,
+    device: OutputDevice,
+}
+
+impl PlayerThread {
+    fn new(commands: Receiver<Command>, events: Sender<Event>, state: Arc<Mutex<State>>) -> Self {
+        let (paused, repeat, volume, muted, replaygain, device) = {
+            let state = state.lock().unwrap();
+            (
+                state.paused,
+                state.repeat,
+                state.volume,
+                state.muted,
+                state.replaygain,
+                state.device.clone(),
+            )
+        };
+
+        Self {
+            commands,
+            events,
+            state,
+            source: None,
+            audio_output: None,
+            preload: None,
+            paused,
+            repeat,
+            ab_loop: None,
+            end: None,
+            volume,
+            muted,
+            replaygain,
+            device,
+        }
+    }
+
+    fn run(mut self) {
         loop {
-            let packet = match format.next_packet() {
-                Ok(packet) => packet,
-                Err(SymError::ResetRequired) => {
-                    // The track list has been changed. Re-examine it and create a new set of decoders,
-                    // then restart the decode loop. This is an advanced feature and it is not
-                    // unreasonable to consider this "the end." As of v0.5.0, the only usage of this is
-                    // for chained OGG physical streams.
-                    unimplemented!();
+            // Nothing to decode, so we can block until the next command arrives
+            let command = if self.source.is_none() || self.paused {
+                match self.commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return,
                 }
-                Err(SymError::IoError(e))
-                    if e.kind() == io::ErrorKind::UnexpectedEof
-                        && e.to_string() == "end of stream" =>
-                {
-                    // File ended
-                    break;
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => {
+                        self.step();
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => return,
                 }
-                Err(err) => {
-                    // A unrecoverable error occurred, halt decoding.
-                    panic!("{}", err);
+            };
+
+            self.handle_command(command);
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Play(index) => self.play_index(index),
+            Command::Pause(paused) => self.set_paused(paused),
+            Command::Seek(seek) => self.seek(seek),
+            Command::Volume(volume) => {
+                self.volume = volume;
+                self.update_gain();
+                self.emit(Event::VolumeChanged);
+            }
+            Command::Mute(muted) => {
+                self.muted = muted;
+                self.update_gain();
+                self.emit(Event::VolumeChanged);
+            }
+            Command::Repeat(repeat) => self.repeat = repeat,
+            Command::AbLoop(ab_loop) => self.ab_loop = ab_loop,
+            Command::ReplayGain(mode) => {
+                self.replaygain = mode;
+                self.update_gain();
+            }
+            Command::Device(device) => {
+                // The output is opened on the new device when the next packet is decoded
+                self.device = device;
+                self.audio_output = None;
+            }
+            Command::Stop => self.stop(),
+        }
+    }
+
+    /// Pauses the audio output too, so the samples that were already decoded aren't played until
+    /// the player is resumed. While paused, no packets are decoded either (see [run](Self::run)).
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if let Some(audio_output) = self.audio_output.as_mut() {
+            if paused {
+                audio_output.pause();
+            } else {
+                audio_output.resume();
+            }
+        }
+        self.emit(Event::PauseChanged(paused));
+    }
+
+    fn emit(&self, event: Event) {
+        // If the controller is gone, nobody is listening anyway
+        self.events.send(event).ok();
+    }
+
+    fn report(&self, error: Error) {
+        error!("{}", error);
+        self.emit(Event::Error(error));
+    }
+
+    fn gain(&self) -> f32 {
+        let replaygain = self
+            .source
+            .as_ref()
+            .map_or(1.0, |source| source.replaygain_factor(self.replaygain));
+        output_gain(self.volume, self.muted, replaygain)
+    }
+
+    fn update_gain(&mut self) {
+        let gain = self.gain();
+        if let Some(audio_output) = self.audio_output.as_mut() {
+            audio_output.set_gain(gain);
+        }
+    }
+
+    /// Interrupts the current track and starts playing the `index`-th entry of the playlist
+    fn play_index(&mut self, index: usize) {
+        // Whatever is still buffered belongs to the interrupted track
+        if let Some(audio_output) = self.audio_output.as_mut() {
+            audio_output.clear();
+        }
+
+        self.preload = None;
+        self.load(index);
+    }
+
+    /// Opens the `index`-th entry of the playlist and makes it the current source, keeping the
+    /// audio output open so there's no gap between tracks. Entries that can't be opened are
+    /// reported and skipped.
+    fn load(&mut self, mut index: usize) {
+        let (source, start, section) = loop {
+            let entry = self
+                .state
+                .lock()
+                .unwrap()
+                .playlist
+                .get_mut(index)
+                .map(|e| (e.path.clone(), e.start.take(), e.section));
+            let Some((path, start, section)) = entry else {
+                return self.end_of_playlist();
+            };
+
+            // Use the preloaded source if it's still the right one
+            let preloaded = match self.preload.take() {
+                Some(preload) if preload.index == index && preload.path == path => {
+                    preload.source.recv().ok()
                 }
+                _ => None,
             };
 
-            // Consume any new metadata that has been read since the last packet.
-            while !format.metadata().is_latest() {
-                // Pop the old head of the metadata queue.
-                format.metadata().pop();
+            match preloaded.unwrap_or_else(|| Source::open(&path)) {
+                Ok(source) => break (source, start, section),
+                Err(e) => {
+                    self.report(e);
+                    index += 1;
+                }
+            }
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.position = Some(index);
+            state.time_pos = 0.0;
+            state.duration = source.duration();
+            state.chapters = source.chapters.clone();
+            if let (Some(title), Some(entry)) = (&source.title, state.playlist.get_mut(index)) {
+                entry.title = title.clone();
+            }
+        }
+
+        self.source = Some(source);
+        self.end = section.and_then(|(_, end)| end);
+
+        // The samples of the previous track were already written with its own gain
+        self.update_gain();
+        self.emit(Event::TrackChanged);
+
+        if let Some(start) = start.or(section.map(|(start, _)| start)) {
+            self.seek(Seek::Absolute(start));
+        }
+    }
+
+    /// Starts opening the next entry of the playlist in the background, if the current track is
+    /// about to end
+    fn preload_next(&mut self, time_pos: f64) {
+        let Some(duration) = self
+            .end
+            .or_else(|| self.source.as_ref().and_then(Source::duration))
+        else {
+            return;
+        };
+        if self.preload.is_some()
+            || self.repeat == RepeatMode::One
+            || duration - time_pos > PRELOAD_SECONDS
+        {
+            return;
+        }
+
+        let next = {
+            let state = self.state.lock().unwrap();
+            state
+                .position
+                .map(|i| self.next_index(i, state.playlist.len()))
+                .and_then(|i| state.playlist.get(i).map(|entry| (i, entry.path.clone())))
+        };
+
+        if let Some((index, path)) = next {
+            let (sender, source) = crossbeam_channel::bounded(1);
+            let path_ = path.clone();
+            thread::spawn(move || {
+                sender.send(Source::open(&path_)).ok();
+            });
+            self.preload = Some(Preload {
+                index,
+                path,
+                source,
+            });
+        }
+    }
+
+    /// Plays whatever is left in the output buffer and stops
+    fn end_of_playlist(&mut self) {
+        if let Some(audio_output) = self.audio_output.as_mut() {
+            audio_output.flush();
+        }
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.source = None;
+        self.end = None;
+        self.preload = None;
+        self.audio_output = None;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.position = None;
+            state.time_pos = 0.0;
+            state.duration = None;
+            state.chapters.clear();
+        }
+        self.emit(Event::TrackChanged);
+    }
+
+    fn seek(&mut self, seek: Seek) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+
+        let time = match seek {
+            Seek::Relative(seconds) => self.state.lock().unwrap().time_pos + seconds,
+            Seek::Absolute(seconds) => seconds,
+        };
+
+        // Some decoders need to decode a bit of the audio before the position to be primed
+        let target = Time::from(time.max(0.0));
+        let pre_roll = opus::pre_roll(source.decoder.codec_params());
+        let seeked = source.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from((time - pre_roll).max(0.0)),
+                track_id: Some(source.track_id),
+            },
+        );
 
-                // Consume the new metadata at the head of the metadata queue.
-                eprintln!("Got new metadata! {:?}", format.metadata().current());
+        match seeked {
+            Ok(seeked) => {
+                // The decoder may hold state from before the seek
+                source.decoder.reset();
+
+                // The format reader seeks to the packet that contains the requested timestamp,
+                // which may start a bit before it
+                let required_ts = match source.time_base {
+                    Some(time_base) if pre_roll > 0.0 => time_base.calc_timestamp(target),
+                    _ => seeked.required_ts,
+                };
+                source.skip_until = Some(required_ts);
+
+                // Whatever was buffered before the seek shouldn't be heard anymore
+                if let Some(audio_output) = self.audio_output.as_mut() {
+                    audio_output.clear();
+                }
+
+                if let Some(time_base) = source.time_base {
+                    self.state.lock().unwrap().time_pos = seconds(time_base.calc_time(required_ts));
+                }
+                self.emit(Event::Seeked);
             }
+            Err(SymError::SeekError(SeekErrorKind::OutOfRange)) => self.end_of_track(),
+            Err(e) => error!("failed to seek: {}", e),
+        }
+    }
 
-            // If the packet does not belong to the selected track, skip over it.
-            if packet.track_id() != track_id {
+    /// Decodes a single packet
+    fn step(&mut self) {
+        match self.decode_packet() {
+            Decoded::Played(time_pos) => {
+                self.state.lock().unwrap().time_pos = time_pos;
+                match (self.ab_loop, self.end) {
+                    (Some((a, b)), _) if a < b && time_pos >= b => self.seek(Seek::Absolute(a)),
+                    (_, Some(end)) if time_pos >= end => self.end_of_track(),
+                    _ => self.preload_next(time_pos),
+                }
+            }
+            Decoded::Skipped => {}
+            Decoded::EndOfTrack => self.end_of_track(),
+            // Without an output device the next tracks would fail too
+            Decoded::Failed(e @ Error::OutputDevice(_)) => {
+                self.report(e);
+                self.stop();
+            }
+            Decoded::Failed(e) => {
+                self.report(e);
+                self.end_of_track();
+            }
+        }
+    }
+
+    fn end_of_track(&mut self) {
+        self.emit(Event::EndOfFile);
+        let position = self.state.lock().unwrap().position;
+        match position {
+            Some(i) if self.repeat == RepeatMode::One => self.load(i),
+            Some(i) => {
+                let len = self.state.lock().unwrap().playlist.len();
+                self.load(self.next_index(i, len));
+            }
+            None => self.end_of_playlist(),
+        }
+    }
+
+    /// The entry that's played after the `i`-th one, when a playlist of `len` entries is looped
+    /// with [RepeatMode::All]. Otherwise, it may be past the end of the playlist.
+    fn next_index(&self, i: usize, len: usize) -> usize {
+        if self.repeat == RepeatMode::All && i + 1 >= len {
+            0
+        } else {
+            i + 1
+        }
+    }
+
+    fn decode_packet(&mut self) -> Decoded {
+        let gain = self.gain();
+        let Some(source) = self.source.as_mut() else {
+            return Decoded::EndOfTrack;
+        };
+
+        let packet = match source.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymError::ResetRequired) => {
+                // The track list has been changed. As of v0.5.0, the only usage of this is for
+                // chained OGG physical streams, like internet radios that start a new logical
+                // stream for every song. The new stream may have another codec or spec, in which
+                // case the audio output is reopened when the first packet is decoded.
+                if let Err(e) = source.reset() {
+                    return Decoded::Failed(e);
+                }
+                self.state.lock().unwrap().duration = source.duration();
+                return Decoded::Skipped;
+            }
+            Err(SymError::IoError(e))
+                if e.kind() == io::ErrorKind::UnexpectedEof && e.to_string() == "end of stream" =>
+            {
+                // File ended
+                return Decoded::EndOfTrack;
+            }
+            Err(err) => {
+                // A unrecoverable error occurred, halt decoding.
+                return Decoded::Failed(Error::Decoder(err));
+            }
+        };
+
+        // Consume any new metadata that has been read since the last packet.
+        while !source.format.metadata().is_latest() {
+            // Pop the old head of the metadata queue.
+            source.format.metadata().pop();
+
+            // Consume the new metadata at the head of the metadata queue.
+            let metadata = source.format.metadata();
+            let Some(revision) = metadata.current() else {
                 continue;
+            };
+
+            if let Some(title) = title_from_metadata(revision) {
+                let mut state = self.state.lock().unwrap();
+                if let Some(entry) = state.position.and_then(|i| state.playlist.get_mut(i)) {
+                    entry.title = title;
+                }
+                self.events.send(Event::TrackChanged).ok();
             }
 
-            match decoder.decode(&packet) {
-                Ok(decoded) => {
-                    // If the audio output is not open, try to open it.
-                    if audio_output.is_none() {
-                        // Get the audio buffer specification. This is a description of the decoded
-                        // audio buffer's sample format and sample rate.
-                        let spec = *decoded.spec();
+            if let Some(replaygain) = ReplayGain::from_metadata(revision) {
+                source.replaygain = Some(replaygain);
+                if let Some(audio_output) = self.audio_output.as_mut() {
+                    let replaygain = replaygain.factor(self.replaygain);
+                    audio_output.set_gain(output_gain(self.volume, self.muted, replaygain));
+                }
+            }
+        }
 
-                        // Get the capacity of the decoded buffer. Note that this is capacity, not
-                        // length! The capacity of the decoded buffer is constant for the life of the
-                        // decoder, but the length is not.
-                        let duration = decoded.capacity() as u64;
+        // If the packet does not belong to the selected track, skip over it.
+        if packet.track_id() != source.track_id {
+            return Decoded::Skipped;
+        }
 
-                        // Try to open the audio output.
-                        audio_output.replace(CpalAudioOutput::try_open(spec, duration).unwrap());
-                    } else {
-                        // TODO: Check the audio spec. and duration hasn't changed.
+        match source.decoder.decode(&packet) {
+            Ok(decoded) => {
+                // Get the audio buffer specification. This is a description of the decoded
+                // audio buffer's sample format and sample rate.
+                let spec = *decoded.spec();
+
+                // Get the capacity of the decoded buffer. Note that this is capacity, not
+                // length! The capacity of the decoded buffer is constant for the life of the
+                // decoder, but the length is not.
+                let duration = decoded.capacity() as u64;
+
+                // If the audio output is not open, try to open it. Once it's open, it's kept
+                // between tracks and adapts itself when the spec changes.
+                if self.audio_output.is_none() {
+                    // Try to open the audio output.
+                    let mut audio_output = match output::try_open(spec, duration, &self.device) {
+                        Ok(audio_output) => audio_output,
+                        Err(e) => return Decoded::Failed(e.into()),
+                    };
+                    audio_output.set_gain(gain);
+                    self.audio_output.replace(audio_output);
+                }
+
+                // Write the decoded audio samples to the audio output if the presentation timestamp
+                // for the packet is >= the seeked position (0 if not seeking).
+                // Timestamps are counted in frames for most formats, but Matroska counts them in
+                // milliseconds, so the difference is converted with the track's time base.
+                let skip = match source.skip_until {
+                    Some(required_ts) => {
+                        let before = required_ts.saturating_sub(packet.ts());
+                        let skip = match source.time_base {
+                            Some(time_base) => (seconds(time_base.calc_time(before))
+                                * spec.rate as f64)
+                                .round() as usize,
+                            None => before as usize,
+                        };
+                        if skip >= decoded.frames() {
+                            // The whole packet comes before the seeked position
+                            return Decoded::Skipped;
+                        }
+                        source.skip_until = None;
+                        skip
                     }
+                    None => 0,
+                };
 
-                    // Write the decoded audio samples to the audio output if the presentation timestamp
-                    // for the packet is >= the seeked position (0 if not seeking).
-                    if let Some(audio_output) = audio_output.as_mut() {
-                        audio_output.write(decoded).unwrap()
+                if let Some(audio_output) = self.audio_output.as_mut() {
+                    let written = if skip == 0 {
+                        audio_output.write(decoded)
+                    } else {
+                        // Drop the frames before the seeked position
+                        let mut trimmed = decoded.make_equivalent::<f32>();
+                        decoded.convert(&mut trimmed);
+                        trimmed.shift(skip);
+                        audio_output.write(AudioBufferRef::F32(Cow::Borrowed(&trimmed)))
+                    };
+                    if let Err(e) = written {
+                        self.audio_output = None;
+                        return Decoded::Failed(e.into());
                     }
                 }
-                Err(SymError::IoError(_)) => {
-                    // The packet failed to decode due to an IO error, skip the packet.
-                    continue;
-                }
-                Err(SymError::DecodeError(_)) => {
-                    // The packet failed to decode due to invalid data, skip the packet.
-                    continue;
+
+                match source.time_base {
+                    Some(time_base) => Decoded::Played(seconds(time_base.calc_time(packet.ts()))),
+                    None => Decoded::Skipped,
                 }
-                Err(err) => {
-                    // An unrecoverable error occurred, halt decoding.
-                    panic!("{}", err);
+            }
+            Err(SymError::IoError(_)) => {
+                // The packet failed to decode due to an IO error, skip the packet.
+                Decoded::Skipped
+            }
+            Err(SymError::DecodeError(_)) => {
+                // The packet failed to decode due to invalid data, skip the packet.
+                Decoded::Skipped
+            }
+            Err(err) => {
+                // An unrecoverable error occurred, halt decoding.
+                Decoded::Failed(Error::Decoder(err))
+            }
+        }
+    }
+}
+
+/// The factor samples are multiplied by before they're written to the output
+fn output_gain(volume: i64, muted: bool, replaygain: f32) -> f32 {
+    if muted {
+        0.0
+    } else {
+        volume as f32 / 100.0 * replaygain
+    }
+}
+
+fn seconds(time: Time) -> f64 {
+    time.seconds as f64 + time.frac
+}
+
+/// Cues have no standard way of naming them, so they're named after their index when there's no
+/// title tag
+fn chapter_from_cue(cue: &Cue, time_base: TimeBase) -> Chapter {
+    let title = cue
+        .tags
+        .iter()
+        .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+        .map_or_else(
+            || format!("Chapter {}", cue.index),
+            |tag| tag.value.to_string(),
+        );
+    Chapter {
+        title,
+        time: seconds(time_base.calc_time(cue.start_ts)),
+    }
+}
+
+/// Builds a title like "Artist - Title" from the tags in the metadata
+fn title_from_metadata(metadata: &MetadataRevision) -> Option<String> {
+    let tag = |key| {
+        metadata
+            .tags()
+            .iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string())
+    };
+
+    match (tag(StandardTagKey::Artist), tag(StandardTagKey::TrackTitle)) {
+        (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+        (None, Some(title)) => Some(title),
+        _ => None,
+    }
+}
+
+/// Audio downloaded by yt-dlp, in its original format and without any transcoding. Usually that's
+/// Opus in a WebM container.
+struct YtDlpStream {
+    child: process::Child,
+    stdout: process::ChildStdout,
+}
+
+impl YtDlpStream {
+    fn spawn(url: &str) -> Result<Self> {
+        let mut child = process::Command::new("yt-dlp")
+            .args(["--quiet", "--no-progress", "--no-playlist"])
+            .args(["-f", "bestaudio[ext=webm]/bestaudio[acodec=opus]/bestaudio"])
+            .args(["-o", "-", url])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| Error::External(format!("Failed to run yt-dlp: {}", e)))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| Error::External("Failed to read the output of yt-dlp".into()))?;
+        Ok(Self { child, stdout })
+    }
+}
+
+impl io::Read for YtDlpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            // The stream ended, but it may have been because yt-dlp failed
+            let status = self.child.wait()?;
+            if !status.success() {
+                let mut stderr = String::new();
+                if let Some(pipe) = self.child.stderr.as_mut() {
+                    pipe.read_to_string(&mut stderr).ok();
                 }
+                return Err(io::Error::other(format!(
+                    "yt-dlp failed: {}",
+                    stderr.trim()
+                )));
             }
         }
-    });
+        Ok(n)
+    }
+}
+
+impl Drop for YtDlpStream {
+    fn drop(&mut self) {
+        // The track may be dropped before it's fully downloaded
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
 }
 
 fn mss_from_path(mut path: &str) -> Result<(MediaSourceStream, Hint)> {
@@ -149,38 +814,46 @@ fn mss_from_path(mut path: &str) -> Result<(MediaSourceStream, Hint)> {
     let mut hint = Hint::default();
     let src: Box<dyn MediaSource> =
         if force_ytdlp || path.starts_with("http://") || path.starts_with("https://") {
-            // Get urls from yt-dlp
-            let ytdlp_output = Command::new("yt-dlp")
-                .args(["-g", path])
-                .output()
-                .unwrap()
-                .stdout;
-            let ytdlp_output = String::from_utf8(ytdlp_output).unwrap();
-            let ytdlp_urls = ytdlp_output.lines();
-
-            // Get ffmpeg mpegts stream.
-            let mut ffmpeg = Command::new("ffmpeg");
-            for url in ytdlp_urls {
-                ffmpeg.args(["-i", url]);
-            }
-            ffmpeg
-                .args(["-f", "mp3"]) // FIXME: don't do this. If you know how to do better please tell me how. Symphonia still doesn't support opus afaik.
-                .arg("-")
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .stdin(Stdio::null());
-            let mut ffmpeg = ffmpeg.spawn().unwrap();
-            let src = ffmpeg.stdout.take().unwrap();
-
-            hint.with_extension("mp3");
-            Box::new(ReadOnlySource::new(src))
+            // The format is detected by probing, since yt-dlp may fall back to something other
+            // than WebM
+            Box::new(ReadOnlySource::new(YtDlpStream::spawn(path)?))
         } else {
             if let Some(ext) = Path::new(path).extension().and_then(|s| s.to_str()) {
                 hint.with_extension(ext);
             }
-            Box::new(File::open(path).expect("failed to open media"))
+            Box::new(File::open(path)?)
         };
 
     let mss = MediaSourceStream::new(src, Default::default());
     Ok((mss, hint))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::PlaylistEntry;
+
+    #[test]
+    fn test_load_skips_entries_that_cant_be_opened() {
+        // Enough entries to overflow the stack if each one took a stack frame
+        let missing = 100_000;
+        let (_commands, receiver) = crossbeam_channel::unbounded();
+        let (sender, events) = crossbeam_channel::unbounded();
+        let state = Arc::new(Mutex::new(State {
+            playlist: (0..missing)
+                .map(|i| PlaylistEntry::new(&format!("/nonexistent/{}.mp3", i)))
+                .collect(),
+            ..Default::default()
+        }));
+
+        let mut player = PlayerThread::new(receiver, sender, state.clone());
+        player.load(0);
+
+        assert_eq!(state.lock().unwrap().position, None);
+        let errors = events
+            .try_iter()
+            .filter(|event| matches!(event, Event::Error(Error::Io(_))))
+            .count();
+        assert_eq!(errors, missing);
+    }
+}
@@ -1,4 +1,12 @@
-use crate::{command, error::Result, events, player::Player, rect_ops::RectOps};
+use crate::{
+    command,
+    config::Config,
+    error::Result,
+    events,
+    player::{shuffle::ShuffleMode, Player, RepeatMode},
+    rect_ops::RectOps,
+    session::Session,
+};
 
 mod now_playing;
 use now_playing::NowPlaying;
@@ -11,6 +19,11 @@ use super::{
     App, Mode,
 };
 
+/// How much SpeedUp and SpeedDown change the playback speed
+const SPEED_STEP: f64 = 0.1;
+const MIN_SPEED: f64 = 0.3;
+const MAX_SPEED: f64 = 4.0;
+
 #[derive(Debug, Default)]
 pub enum Selected {
     #[default]
@@ -40,6 +53,28 @@ impl<'a> AppScreen<'a> {
         self.selected = selection;
     }
 
+    /// The state of the player and the song selected in the browse screen
+    pub fn session(&self, app: &App) -> Result<Session> {
+        let mut session = Session::from_player(&app.player)?;
+        session.shuffle = app.shuffle.mode();
+        session.unshuffled = app.shuffle.original().to_vec();
+        (session.playlist, session.song) = self.browse.selection();
+        Ok(session)
+    }
+
+    /// Restores the state of the player and selects the song that was selected
+    pub fn restore_session(&mut self, app: &mut App, session: &Session) -> Result<()> {
+        session.restore(&mut app.player)?;
+        app.shuffle
+            .restore(session.shuffle, session.unshuffled.clone());
+        self.now_playing.shuffle = session.shuffle;
+        if let Some(playlist) = &session.playlist {
+            self.browse.select_song(playlist, session.song)?;
+        }
+        self.now_playing.update(&app.player);
+        Ok(())
+    }
+
     pub fn pass_event_down(&mut self, app: &mut App, event: events::Event) -> Result<()> {
         match self.selected {
             Selected::Browse => self.browse.handle_event(app, event),
@@ -51,6 +86,7 @@ impl<'a> AppScreen<'a> {
         use command::Command::*;
         match cmd {
             Quit => {
+                self.browse.save_songs()?;
                 app.quit();
             }
             SeekForward => {
@@ -78,9 +114,29 @@ impl<'a> AppScreen<'a> {
                 self.now_playing.update(&app.player);
             }
             ToggleLoop => {
-                app.player.toggle_loop_file()?;
+                let mode = match app.player.repeat_mode()? {
+                    RepeatMode::One => RepeatMode::Off,
+                    _ => RepeatMode::One,
+                };
+                app.player.set_repeat(mode)?;
+                self.now_playing.update(&app.player);
+            }
+            CycleRepeat => {
+                let mode = app.player.repeat_mode()?.next();
+                app.player.set_repeat(mode)?;
                 self.now_playing.update(&app.player);
             }
+            Shuffle => {
+                let mode = match app.shuffle.mode() {
+                    ShuffleMode::Off => ShuffleMode::Songs,
+                    _ => ShuffleMode::Off,
+                };
+                self.set_shuffle(app, mode)?;
+            }
+            CycleShuffle => {
+                let mode = app.shuffle.mode().next();
+                self.set_shuffle(app, mode)?;
+            }
             VolumeUp => {
                 app.player.add_volume(5)?;
                 self.now_playing.update(&app.player);
@@ -93,11 +149,62 @@ impl<'a> AppScreen<'a> {
                 app.player.toggle_mute()?;
                 self.now_playing.update(&app.player);
             }
+            SpeedUp => {
+                self.add_speed(app, SPEED_STEP)?;
+            }
+            SpeedDown => {
+                self.add_speed(app, -SPEED_STEP)?;
+            }
+            SpeedReset => {
+                app.player.set_speed(1.0)?;
+                self.now_playing.update(&app.player);
+            }
+            NextChapter => {
+                app.player
+                    .chapter_next()
+                    .unwrap_or_else(|_| app.notify_err("No next chapter"));
+                self.now_playing.update(&app.player);
+            }
+            PrevChapter => {
+                app.player
+                    .chapter_prev()
+                    .unwrap_or_else(|_| app.notify_err("No previous chapter"));
+                self.now_playing.update(&app.player);
+            }
+            AbLoop => {
+                let time = app.player.precise_time_pos()?;
+                let (a, b) = match app.player.ab_loop()? {
+                    (None, _) => (Some(time), None),
+                    (Some(a), None) if time <= a => {
+                        app.notify_info("The B point has to be after the A point");
+                        return Ok(());
+                    }
+                    (Some(a), None) => (Some(a), Some(time)),
+                    (Some(_), Some(_)) => (None, None),
+                };
+                app.player.set_ab_loop(a, b)?;
+                self.now_playing.update(&app.player);
+            }
             _ => self.pass_event_down(app, events::Event::Command(cmd))?,
         }
         Ok(())
     }
 
+    fn set_shuffle(&mut self, app: &mut App, mode: ShuffleMode) -> Result<()> {
+        app.shuffle.set_mode(&mut app.player, mode)?;
+        self.now_playing.shuffle = mode;
+        self.playlist.update(app)?;
+        Ok(())
+    }
+
+    fn add_speed(&mut self, app: &mut App, step: f64) -> Result<()> {
+        // Rounded, so adding steps doesn't pile up floating point errors
+        let speed = ((app.player.speed()? + step) * 10.0).round() / 10.0;
+        app.player.set_speed(speed.clamp(MIN_SPEED, MAX_SPEED))?;
+        self.now_playing.update(&app.player);
+        Ok(())
+    }
+
     /// Returns (app chunk, now_playing chunk)
     fn subcomponent_chunks(frame: Rect) -> (Rect, Rect) {
         frame.split_bottom(2)
@@ -135,15 +242,41 @@ impl<'a> Component for AppScreen<'a> {
                     self.select(Selected::Browse);
                 }
                 KeyCode::Char('2') if self.mode() == Mode::Normal => {
-                    self.playlist.update(&app.player)?;
+                    self.browse.save_songs()?;
+                    self.playlist.update(app)?;
                     self.select(Selected::Playlist);
                 }
                 _ => self.pass_event_down(app, event)?,
             },
             SecondTick => {
+                self.now_playing.update_time(&app.player);
+                self.pass_event_down(app, event)?;
+            }
+            TrackChanged => {
+                // The weighted shuffle avoids the songs that were played recently
+                if let Ok(path) = app
+                    .player
+                    .playlist_position()
+                    .and_then(|i| app.player.playlist_track_path(i))
+                {
+                    app.shuffle.song_played(path);
+                }
+                if Config::global().save_session_on_track_change {
+                    if let Err(e) = self
+                        .session(app)
+                        .and_then(|session| session.save(&Session::default_path()))
+                    {
+                        app.notify_err(format!("Couldn't save the session: {}", e));
+                    }
+                }
+                self.now_playing.update(&app.player);
+                self.pass_event_down(app, event)?;
+            }
+            PauseChanged(_) | Seeked | EndOfFile | VolumeChanged | QueueChanged => {
                 self.now_playing.update(&app.player);
                 self.pass_event_down(app, event)?;
             }
+            PlayerError(e) => app.notify_err(e.clone()),
             _ => self.pass_event_down(app, event)?,
         }
         Ok(())
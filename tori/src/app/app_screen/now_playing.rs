@@ -13,7 +13,7 @@ use crate::{
     },
     error::Result,
     events,
-    player::Player,
+    player::{shuffle::ShuffleMode, Player, RepeatMode},
     rect_ops::RectOps,
 };
 
@@ -33,18 +33,31 @@ pub struct NowPlaying {
     pub time_pos: i64,
     pub time_rem: i64,
     pub paused: bool,
-    pub loop_file: bool,
+    pub repeat: RepeatMode,
+    /// Set by whoever shuffles the queue, since the player doesn't know about it
+    pub shuffle: ShuffleMode,
     pub volume: i64,
+    /// Playback speed, if it isn't the normal one
+    pub speed: Option<f64>,
+    /// Where the chapters of the song start, in seconds
+    pub chapters: Vec<f64>,
+    pub ab_loop: (Option<f64>, Option<f64>),
 }
 
 impl NowPlaying {
     pub fn update(&mut self, player: &impl Player) {
         self.media_title = player.media_title().unwrap_or_default();
-        self.percentage = player.percent_pos().unwrap_or_default();
-        self.time_pos = player.time_pos().unwrap_or_default();
-        self.time_rem = player.time_remaining().unwrap_or_default();
+        self.update_time(player);
         self.paused = player.paused().unwrap_or_default();
-        self.loop_file = player.looping_file().unwrap_or_default();
+        self.repeat = player.repeat_mode().unwrap_or_default();
+        self.speed = player.speed().ok().filter(|&speed| speed != 1.0);
+        self.chapters = player
+            .chapters()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|chapter| chapter.time)
+            .collect();
+        self.ab_loop = player.ab_loop().unwrap_or_default();
 
         self.volume = if player.muted().unwrap_or(false) {
             0
@@ -53,6 +66,14 @@ impl NowPlaying {
         };
     }
 
+    /// Updates only the playback position, which changes all the time, so the player doesn't send
+    /// events for it
+    pub fn update_time(&mut self, player: &impl Player) {
+        self.percentage = player.percent_pos().unwrap_or_default();
+        self.time_pos = player.time_pos().unwrap_or_default();
+        self.time_rem = player.time_remaining().unwrap_or_default();
+    }
+
     fn playback_strs(&self) -> (String, String) {
         let playback_left_str = format!("⏴︎ {:02}:{:02} ", self.time_pos / 60, self.time_pos % 60);
         let playback_right_str = format!("-{:02}:{:02} ⏵︎", self.time_rem / 60, self.time_rem % 60);
@@ -133,11 +154,27 @@ impl Component for NowPlaying {
                 ));
             }
 
-            if self.loop_file {
-                parts.push(Span::styled(
-                    "[looping] ",
-                    Style::default().fg(Color::DarkGray),
-                ));
+            match self.repeat {
+                RepeatMode::Off => {}
+                RepeatMode::One => parts.push(Span::raw("🔂 ")),
+                RepeatMode::All => parts.push(Span::raw("🔁 ")),
+            }
+
+            match self.shuffle {
+                ShuffleMode::Off => {}
+                ShuffleMode::Songs => parts.push(Span::raw("🔀 ")),
+                ShuffleMode::Albums => parts.push(Span::raw("🔀 albums ")),
+                ShuffleMode::Weighted => parts.push(Span::raw("🔀 weighted ")),
+            }
+
+            if let Some(speed) = self.speed {
+                parts.push(Span::raw(format!("{}x ", speed)));
+            }
+
+            match self.ab_loop {
+                (Some(_), None) => parts.push(Span::raw("[A-] ")),
+                (Some(_), Some(_)) => parts.push(Span::raw("[A-B] ")),
+                _ => {}
             }
 
             parts.push(Span::styled(
@@ -184,6 +221,17 @@ impl Component for NowPlaying {
                 .repeat(chunks.playback_bar.width as usize)
                 .chars()
                 .collect();
+            // A tick where each chapter starts
+            let duration = (self.time_pos + self.time_rem) as f64;
+            if duration > 0.0 {
+                for &time in &self.chapters {
+                    let j = (time / duration * s.len() as f64) as usize;
+                    if time > 0.0 && j < s.len() {
+                        s[j] = '┼';
+                    }
+                }
+            }
+
             let i = (self.percentage as usize * s.len() / 100)
                 .min(s.len() - 1)
                 .max(0);
@@ -28,7 +28,7 @@ use songs::SongsPane;
 
 use super::Mode;
 use super::{component::MouseHandler, modal::HotkeyModal};
-use crate::app::modal::{self, ConfirmationModal, HelpModal, InputModal, Modal};
+use crate::app::modal::{self, ConfirmationModal, HelpModal, InputModal, Modal, SelectModal};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ModalType {
@@ -39,8 +39,12 @@ enum ModalType {
     AddPlaylist,
     RenamePlaylist { playlist: String },
     DeletePlaylist { playlist: String },
-    RenameSong { playlist: String, index: usize },
-    DeleteSong { playlist: String, index: usize },
+    RenameSong { index: usize },
+    DeleteSong { index: usize },
+    OutputDevice,
+    Chapter,
+    ImportPlaylist,
+    ExportPlaylist { playlist: String },
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -86,6 +90,30 @@ impl<'a> BrowseScreen<'a> {
         self.songs.update_from_playlist_pane(&self.playlists)
     }
 
+    /// Saves the songs that were moved in the songs pane, see [SongsPane::save]
+    pub fn save_songs(&mut self) -> Result<()> {
+        self.songs.save()
+    }
+
+    /// The name of the selected playlist, and the index of the selected song in it
+    pub fn selection(&self) -> (Option<String>, Option<usize>) {
+        (
+            self.playlists.selected_item().map(str::to_string),
+            self.songs.selected_index(),
+        )
+    }
+
+    /// Selects the `song`-th song of `playlist`, if they're still there
+    pub fn select_song(&mut self, playlist: &str, song: Option<usize>) -> Result<()> {
+        if self.playlists.select_named(playlist) {
+            self.reload_songs()?;
+            if let Some(song) = song {
+                self.songs.select_song(song);
+            }
+        }
+        Ok(())
+    }
+
     /// Passes the event down to the currently selected pane.
     fn pass_event_down(&mut self, app: &mut App, event: Event) -> Result<()> {
         use BrowsePane::*;
@@ -163,7 +191,10 @@ impl<'a> BrowseScreen<'a> {
                             app.notify_err(format!("Playlist name cannot contain '{}' !", c));
                         }
                         Err(RenamePlaylistError::IOError(e)) => return Err(e.into()),
-                        Ok(_) => self.playlists.reload_from_dir()?,
+                        Ok(_) => {
+                            self.playlists.reload_from_dir()?;
+                            self.reload_songs()?;
+                        }
                     }
                     self.selected_pane = BrowsePane::Playlists;
                 }
@@ -189,36 +220,66 @@ impl<'a> BrowseScreen<'a> {
                 }
 
                 // RenameSong
-                (
-                    RenameSong {
-                        playlist: _,
-                        index: _,
-                    },
-                    Quit,
-                ) => {
+                (RenameSong { index: _ }, Quit) => {
                     self.selected_pane = BrowsePane::Songs;
                 }
-                (RenameSong { playlist, index }, Commit(new_name)) => {
-                    playlist_management::rename_song(playlist, *index, &new_name)?;
-                    self.reload_songs()?;
+                (RenameSong { index }, Commit(new_name)) => {
+                    self.songs.rename_song(*index, &new_name)?;
                     self.selected_pane = BrowsePane::Songs;
                 }
 
                 // DeleteSong
-                (
-                    DeleteSong {
-                        playlist: _,
-                        index: _,
-                    },
-                    Quit,
-                ) => {
+                (DeleteSong { index: _ }, Quit) => {
                     self.selected_pane = BrowsePane::Songs;
                 }
-                (DeleteSong { playlist, index }, Commit(_)) => {
-                    playlist_management::delete_song(playlist, *index)?;
-                    self.reload_songs()?;
+                (DeleteSong { index }, Commit(_)) => {
+                    self.songs.delete_song(*index)?;
+                    self.selected_pane = BrowsePane::Songs;
+                }
+
+                // OutputDevice
+                (OutputDevice, Quit) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (OutputDevice, Commit(device)) => {
+                    app.player.set_output_device(&device)?;
+                    app.notify_ok(format!("Now playing on {}", device));
+                    self.selected_pane = BrowsePane::Songs;
+                }
+
+                // Chapter
+                (Chapter, Quit) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (Chapter, Commit(item)) => {
+                    let chapters = app.player.chapters()?;
+                    if let Some(i) = chapters.iter().position(|c| chapter_item(c) == item) {
+                        app.player.set_chapter(i)?;
+                    }
                     self.selected_pane = BrowsePane::Songs;
                 }
+
+                // ImportPlaylist
+                (ImportPlaylist, Quit) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                }
+                (ImportPlaylist, Commit(path)) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                    let playlist = playlist_management::import_playlist(&path)?;
+                    self.playlists.reload_from_dir()?;
+                    self.select_song(&playlist, None)?;
+                    app.notify_ok(format!("Imported {} as '{}'", path, playlist));
+                }
+
+                // ExportPlaylist
+                (ExportPlaylist { playlist: _ }, Quit) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                }
+                (ExportPlaylist { playlist }, Commit(path)) => {
+                    playlist_management::export_playlist(playlist, &path)?;
+                    app.notify_ok(format!("Exported '{}' to {}", playlist, path));
+                    self.selected_pane = BrowsePane::Playlists;
+                }
             }
         } else {
             panic!("Please don't call BrowseScreen::handle_modal_message without a selected modal");
@@ -239,6 +300,31 @@ impl<'a> BrowseScreen<'a> {
             OpenHotkeyModal => {
                 self.open_hotkey_modal();
             }
+            SelectOutputDevice => {
+                let devices = app.player.output_devices()?;
+                let current = app.player.output_device().unwrap_or_default();
+                self.selected_pane = BrowsePane::Modal(ModalType::OutputDevice);
+                self.modal =
+                    Box::new(SelectModal::new(" Output device ", devices).select(&current));
+            }
+            SelectChapter => {
+                let chapters = app.player.chapters()?;
+                if chapters.is_empty() {
+                    app.notify_info("This song has no chapters");
+                    return Ok(());
+                }
+                let current = app
+                    .player
+                    .chapter()?
+                    .and_then(|i| chapters.get(i))
+                    .map(chapter_item)
+                    .unwrap_or_default();
+                self.selected_pane = BrowsePane::Modal(ModalType::Chapter);
+                self.modal = Box::new(
+                    SelectModal::new(" Chapters ", chapters.iter().map(chapter_item).collect())
+                        .select(&current),
+                );
+            }
             SelectRight | SelectLeft => self.select_next_panel(),
             // TODO: this should probably be in each pane's handle_event, somehow
             Add => match self.selected_pane {
@@ -277,18 +363,10 @@ impl<'a> BrowseScreen<'a> {
                     }
                 }
                 BrowsePane::Songs => {
-                    if let (Some(playlist), Some(index)) =
-                        (self.playlists.selected_item(), self.songs.selected_index())
-                    {
+                    if let Some(index) = self.songs.selected_index() {
                         // kind of a hack, sorry
                         // couldn't figure out how to downcast Box<dyn Modal> to Box<InputModal>
-                        self.open_modal(
-                            "<placeholder>",
-                            ModalType::RenameSong {
-                                playlist: playlist.to_owned(),
-                                index,
-                            },
-                        );
+                        self.open_modal("<placeholder>", ModalType::RenameSong { index });
 
                         let song_title = self.songs.selected_item().unwrap().title.clone();
                         self.modal = Box::new(
@@ -310,23 +388,41 @@ impl<'a> BrowseScreen<'a> {
                     }
                 }
                 BrowsePane::Songs => {
-                    if let (Some(playlist), Some(index)) =
-                        (self.playlists.selected_item(), self.songs.selected_index())
-                    {
+                    if let Some(index) = self.songs.selected_index() {
                         let title = format!(
                             "Do you really want to delete '{}'?",
                             self.songs.selected_item().unwrap().title
                         );
-                        let modal_type = ModalType::DeleteSong {
-                            playlist: playlist.to_owned(),
-                            index,
-                        };
+                        let modal_type = ModalType::DeleteSong { index };
                         self.open_confirmation(title.as_str(), modal_type)
                             .apply_style(Style::default().fg(Color::LightRed));
                     }
                 }
                 _ => {}
             },
+            ImportPlaylist => {
+                self.open_modal(
+                    " Import playlist (.m3u, .pls, .xspf or .cue) ",
+                    ModalType::ImportPlaylist,
+                );
+            }
+            ExportPlaylist => {
+                if let Some(playlist) = self.playlists.selected_item() {
+                    let playlist = playlist.to_owned();
+                    self.open_modal(
+                        "<placeholder>",
+                        ModalType::ExportPlaylist {
+                            playlist: playlist.clone(),
+                        },
+                    );
+                    self.modal = Box::new(
+                        InputModal::new(" Export to (.m3u8, .pls, .xspf or .cue) ")
+                            .set_input(format!("{}.xspf", playlist)),
+                    );
+                } else {
+                    app.notify_err("Please select a playlist to export");
+                }
+            }
             OpenInEditor => self.playlists.open_editor_for_selected(app)?,
             _ => self.pass_event_down(app, Event::Command(cmd))?,
         }
@@ -434,12 +530,19 @@ impl<'t> Component for BrowseScreen<'t> {
                 }
                 app.notify_ok(format!("\"{}\" was added to {}", song, playlist));
             }
-            SecondTick => {}
+            AddSongFailed(e) => app.notify_err(e),
+            SecondTick | TrackChanged | PauseChanged(_) | Seeked | EndOfFile | VolumeChanged
+            | QueueChanged | PlayerError(_) => {}
             ChangedPlaylist => {
                 self.reload_songs()?;
             }
             Terminal(event) => self.handle_terminal_event(app, event)?,
         }
+
+        // Moved songs are saved once the songs pane loses focus
+        if self.selected_pane != BrowsePane::Songs {
+            self.save_songs()?;
+        }
         Ok(())
     }
 
@@ -464,6 +567,7 @@ impl<'a> MouseHandler for BrowseScreen<'a> {
         if hchunks[0].contains(event.column, event.row) {
             if let MouseEventKind::Down(_) = event.kind {
                 self.selected_pane = BrowsePane::Playlists;
+                self.save_songs()?;
             }
             self.playlists.handle_mouse(app, hchunks[0], event)
         } else {
@@ -474,3 +578,9 @@ impl<'a> MouseHandler for BrowseScreen<'a> {
         }
     }
 }
+
+/// How a chapter is shown in the chapter modal
+fn chapter_item(chapter: &crate::player::Chapter) -> String {
+    let time = chapter.time as i64;
+    format!("{:02}:{:02} {}", time / 60, time % 60, chapter.title)
+}
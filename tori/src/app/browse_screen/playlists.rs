@@ -40,19 +40,21 @@ impl PlaylistsPane {
             .map_err(|e| format!("Failed to read playlists directory: {}", e))?;
 
         use std::fs::DirEntry;
+        // Files in other formats have to be imported first
         let extract_playlist_name = |entry: StdResult<DirEntry, io::Error>| {
             Ok(entry
                 .unwrap()
                 .file_name()
                 .into_string()
                 .map_err(|filename| format!("File '{:?}' has invalid UTF-8", filename))?
-                .trim_end_matches(".m3u8")
-                .to_string())
+                .strip_suffix(".m3u8")
+                .map(str::to_string))
         };
 
         self.playlists = dir
             .into_iter()
             .map(extract_playlist_name)
+            .filter_map(Result::transpose)
             .collect::<Result<_>>()?;
 
         self.playlists.sort();
@@ -109,6 +111,14 @@ impl PlaylistsPane {
         app.channel.send(Event::ChangedPlaylist).unwrap();
     }
 
+    /// Selects the playlist called `name`, if it's shown. Returns whether it was.
+    pub fn select_named(&mut self, name: &str) -> bool {
+        match self.playlists.iter().position(|p| p == name) {
+            Some(i) => self.shown.select_item(i),
+            None => false,
+        }
+    }
+
     pub fn selected_item(&self) -> Option<&str> {
         self.shown
             .selected_item()
@@ -78,7 +78,7 @@ enum MousePressLocation {
 pub struct SongsPane<'t> {
     /// Generally the name of the playlist
     title: Cow<'t, str>,
-    songs: Vec<m3u::Song>,
+    playlist: m3u::Playlist,
     shown: FilteredList<TableState>,
     sorting_method: SortingMethod,
     filter: String,
@@ -119,28 +119,21 @@ impl<'t> SongsPane<'t> {
     }
 
     pub fn update_from_playlist(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        let file = std::fs::File::open(&path)
-            .map_err(|_| format!("Couldn't open playlist file {}", path.as_ref().display()))?;
-
-        let title = Cow::Owned(
-            path.as_ref()
-                .file_stem()
-                .unwrap()
-                .to_string_lossy()
-                .to_string(),
-        );
-
-        let songs = m3u::Parser::from_reader(file).all_songs()?;
+        // The songs moved in the playlist shown until now are saved, and the new one is shown even
+        // if that fails
+        let saved = self.save();
+        let playlist = m3u::Playlist::from_path(path.as_ref())?;
+        let title = Cow::Owned(playlist.name());
         let state = self.state();
 
         // Update stuff
         self.title = title;
-        self.songs = songs;
+        self.playlist = playlist;
         self.filter.clear();
         self.refresh_shown();
 
         // Try to reuse previous state
-        if matches!(state.selected(), Some(i) if i < self.songs.len()) {
+        if matches!(state.selected(), Some(i) if i < self.playlist.len()) {
             self.set_state(state);
         } else if self.shown.items.is_empty() {
             self.select_index(None);
@@ -148,21 +141,31 @@ impl<'t> SongsPane<'t> {
             self.select_index(Some(0));
         }
 
-        Ok(())
+        saved
     }
 
     fn refresh_shown(&mut self) {
+        let query = self
+            .filter
+            .get(1..)
+            .unwrap_or_default()
+            .trim_end_matches('\n')
+            .to_lowercase();
         let pred = |s: &m3u::Song| {
             self.filter.is_empty()
-                || s.title
-                    .to_lowercase()
-                    .contains(&self.filter[1..].trim_end_matches('\n').to_lowercase())
-                || s.path
-                    .to_lowercase()
-                    .contains(&self.filter[1..].trim_end_matches('\n').to_lowercase())
+                || [
+                    Some(&s.title),
+                    Some(&s.path),
+                    s.artist.as_ref(),
+                    s.album.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|field| field.to_lowercase().contains(&query))
         };
-        let comparison = |i, j| compare_songs(i, j, &self.songs, self.sorting_method);
-        self.shown.filter(&self.songs, pred, comparison);
+        let songs = self.playlist.songs();
+        let comparison = |i, j| compare_songs(i, j, songs, self.sorting_method);
+        self.shown.filter(songs, pred, comparison);
     }
 
     fn next_sorting_method(&mut self) {
@@ -221,17 +224,38 @@ impl<'t> SongsPane<'t> {
             SelectPrev => self.select_prev(),
             QueueSong => {
                 if let Some(song) = self.selected_item() {
-                    app.player.queue(&song.path)?;
+                    queue_song(&mut app.player, &song)?;
                 }
             }
             QueueShown => {
                 for &i in self.shown.items.iter() {
-                    let path = self.songs[i].path.as_str();
-                    app.player.queue(path)?;
+                    if let Some(song) = self.playlist.resolved_song(i) {
+                        queue_song(&mut app.player, &song)?;
+                    }
                 }
             }
-            Shuffle => {
-                app.player.shuffle()?;
+            PlayNext => {
+                let songs: Vec<m3u::Song> = if self.filter.is_empty() {
+                    self.selected_item().into_iter().collect()
+                } else {
+                    self.shown
+                        .items
+                        .iter()
+                        .filter_map(|&i| self.playlist.resolved_song(i))
+                        .collect()
+                };
+
+                // Each song is inserted right after the current one, so they're inserted in
+                // reverse to be played in the order they're shown
+                for song in songs.iter().rev() {
+                    queue_song_next(&mut app.player, song)?;
+                }
+
+                match songs.as_slice() {
+                    [] => {}
+                    [song] => app.notify_info(format!("\"{}\" will play next", song.title)),
+                    songs => app.notify_info(format!("{} songs will play next", songs.len())),
+                }
             }
             OpenInBrowser => {
                 if let Some(song) = self.selected_item() {
@@ -259,16 +283,14 @@ impl<'t> SongsPane<'t> {
             }
             SwapSongUp if self.filter.is_empty() => match self.selected_index() {
                 Some(i) if i >= 1 => {
-                    m3u::playlist_management::swap_song(&self.title, i - 1)?;
-                    self.songs.swap(i - 1, i);
+                    self.playlist.move_song(i, i - 1);
                     self.select_prev();
                 }
                 _ => {}
             },
             SwapSongDown if self.filter.is_empty() => match self.selected_index() {
-                Some(i) if i + 1 < self.songs.len() => {
-                    m3u::playlist_management::swap_song(&self.title, i)?;
-                    self.songs.swap(i, i + 1);
+                Some(i) if i + 1 < self.playlist.len() => {
+                    self.playlist.move_song(i, i + 1);
                     self.select_next();
                 }
                 _ => {}
@@ -400,7 +422,10 @@ impl<'t> SongsPane<'t> {
 
     pub fn play_selected(&self, app: &mut App) -> Result<()> {
         if let Some(song) = self.selected_item() {
-            app.player.play(&song.path)?;
+            match song.section() {
+                Some((start, end)) => app.player.play_section(&song.path, start, end)?,
+                None => app.player.play(&song.path)?,
+            }
         }
         Ok(())
     }
@@ -417,13 +442,75 @@ impl<'t> SongsPane<'t> {
         self.shown.state.select(i);
     }
 
-    pub fn selected_item(&self) -> Option<&m3u::Song> {
-        self.shown.selected_item().and_then(|i| self.songs.get(i))
+    /// Selects the `i`-th song of the playlist, if it's shown
+    pub fn select_song(&mut self, i: usize) {
+        self.shown.select_item(i);
+    }
+
+    /// The selected song, with its path resolved so the player can find it
+    pub fn selected_item(&self) -> Option<m3u::Song> {
+        self.shown
+            .selected_item()
+            .and_then(|i| self.playlist.resolved_song(i))
     }
 
     pub fn selected_index(&self) -> Option<usize> {
         self.shown.selected_item()
     }
+
+    /// Writes the songs that were moved since the playlist was last saved. Moving a song only
+    /// changes the playlist in memory, so moving it many rows writes the file once.
+    ///
+    /// Songs added to the file meanwhile are kept, but if it was changed in another way the
+    /// changes here are dropped instead, and the playlist is shown as it is in the file.
+    pub fn save(&mut self) -> Result<()> {
+        let saved = self.playlist.save();
+        if saved.is_err() {
+            self.playlist.discard_changes();
+            let path = self.playlist.path().to_owned();
+            self.update_from_playlist(path).ok();
+        }
+        saved
+    }
+
+    /// Renames the `i`-th song of the playlist and saves it
+    pub fn rename_song(&mut self, i: usize, title: &str) -> Result<()> {
+        self.playlist.rename_song(i, title);
+        self.save()?;
+        self.refresh_shown();
+        Ok(())
+    }
+
+    /// Deletes the `i`-th song of the playlist and saves it. The song after it is selected.
+    pub fn delete_song(&mut self, i: usize) -> Result<()> {
+        self.playlist.remove(i);
+        self.save()?;
+        self.refresh_shown();
+        Ok(())
+    }
+}
+
+/// Queues the song, or only its section of the file, like the tracks of CUE sheets
+fn queue_song(player: &mut impl Player, song: &m3u::Song) -> Result<()> {
+    match song.section() {
+        Some((start, end)) => player.queue_section(&song.path, start, end),
+        None => player.queue(&song.path),
+    }
+}
+
+/// Like [queue_song], but inserts the song right after the one being played
+fn queue_song_next(player: &mut impl Player, song: &m3u::Song) -> Result<()> {
+    let Some((start, end)) = song.section() else {
+        return player.queue_next(&song.path);
+    };
+
+    // Sections are queued at the end and then moved, like mpv does with queue_next
+    let count = player.playlist_count()?;
+    player.queue_section(&song.path, start, end)?;
+    match player.playlist_position() {
+        Ok(i) if i + 1 < count => player.playlist_move(count, i + 1),
+        _ => Ok(()),
+    }
 }
 
 impl<'t> Component for SongsPane<'t> {
@@ -462,28 +549,47 @@ impl<'t> Component for SongsPane<'t> {
             .border_type(BorderType::Plain)
             .border_style(border_style);
 
-        if !self.songs.is_empty() {
+        if !self.playlist.is_empty() {
+            let songs = self.playlist.songs();
+
+            // The artist and album columns are only shown if some song has them
+            let has_artist = songs.iter().any(|s| s.artist.is_some());
+            let has_album = songs.iter().any(|s| s.album.is_some());
+
             // Render songlist
             let songlist: Vec<_> = self
                 .shown
                 .items
                 .iter()
-                .map(|&i| &self.songs[i])
+                .map(|&i| &songs[i])
                 .map(|song| {
-                    Row::new(vec![
-                        format!(" {}", song.title),
-                        format!(
-                            "{}:{:02}",
-                            song.duration.as_secs() / 60,
-                            song.duration.as_secs() % 60
-                        ),
-                    ])
+                    let mut cells = vec![format!(" {}", song.title)];
+                    if has_artist {
+                        cells.push(song.artist.clone().unwrap_or_default());
+                    }
+                    if has_album {
+                        cells.push(song.album.clone().unwrap_or_default());
+                    }
+                    cells.push(format!(
+                        "{}:{:02}",
+                        song.duration.as_secs() / 60,
+                        song.duration.as_secs() % 60
+                    ));
+                    Row::new(cells)
                 })
                 .collect();
             let songlist_len = songlist.len();
 
-            // Render table
-            let widths = [Constraint::Length(chunk.width - 11), Constraint::Length(10)];
+            // Render table. The text columns share the width equally, and the title takes what's
+            // left of the division.
+            let text_width = chunk.width.saturating_sub(11);
+            let text_columns = 1 + has_artist as u16 + has_album as u16;
+            let column_width = text_width / text_columns;
+            let mut widths = vec![Constraint::Length(
+                text_width - column_width * (text_columns - 1),
+            )];
+            widths.extend((1..text_columns).map(|_| Constraint::Length(column_width)));
+            widths.push(Constraint::Length(10));
             let widget = Table::new(songlist, widths)
                 .block(block)
                 .highlight_style(Style::default().bg(Color::Yellow).fg(Color::Black))
@@ -1,9 +1,13 @@
 use super::App;
 use crate::{error::Result, events};
-use std::io;
-use tui::{backend::CrosstermBackend, layout::Rect, Frame};
+use tui::{layout::Rect, Frame};
 
-pub(crate) type MyBackend = CrosstermBackend<io::Stdout>;
+#[cfg(not(test))]
+pub(crate) type MyBackend = tui::backend::CrosstermBackend<std::io::Stdout>;
+
+/// Tests render to a buffer instead of the terminal
+#[cfg(test)]
+pub(crate) type MyBackend = tui::backend::TestBackend;
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 #[repr(i8)]
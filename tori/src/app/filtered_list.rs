@@ -85,6 +85,17 @@ impl<St: Selectable> FilteredList<St> {
     pub fn selected_item(&self) -> Option<usize> {
         self.state.selected().map(|i| self.items[i])
     }
+
+    /// Selects the `item`-th item of the original list, if it's shown. Returns whether it was.
+    pub fn select_item(&mut self, item: usize) -> bool {
+        match self.items.iter().position(|&i| i == item) {
+            Some(position) => {
+                self.state.select(Some(position));
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 fn wrap_inc(x: usize, modulo: usize) -> usize {
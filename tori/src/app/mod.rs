@@ -11,7 +11,7 @@ use std::{
     io,
     time::{self, Duration},
 };
-use tui::{backend::CrosstermBackend, layout::Rect, style::Color, Terminal};
+use tui::{layout::Rect, style::Color, Terminal};
 
 use crate::{
     app::component::Mode,
@@ -19,7 +19,8 @@ use crate::{
     config::Config,
     error::Result,
     events::{self, Channel},
-    player::{DefaultPlayer, Player},
+    player::{shuffle::Shuffle, AnyPlayer, Player},
+    session::Session,
     visualizer::{self, Visualizer},
     widgets::notification::Notification,
 };
@@ -31,6 +32,9 @@ pub mod filtered_list;
 pub mod modal;
 pub mod playlist_screen;
 
+#[cfg(test)]
+mod tests;
+
 use crate::events::Event;
 
 use self::{
@@ -45,7 +49,8 @@ const LOW_EVENT_TIMEOUT: u16 = 17;
 pub struct App<'a> {
     pub channel: Channel,
     terminal: Terminal<MyBackend>,
-    player: DefaultPlayer,
+    player: AnyPlayer,
+    shuffle: Shuffle,
     next_render: time::Instant,
     next_poll_timeout: u16,
     notification: Notification<'a>,
@@ -55,12 +60,18 @@ pub struct App<'a> {
 }
 
 impl<'a> App<'a> {
+    #[cfg(not(test))]
     pub fn new() -> Result<App<'a>> {
         let stdout = io::stdout();
-        let backend = CrosstermBackend::new(stdout);
-        let terminal = Terminal::new(backend)?;
+        let backend = tui::backend::CrosstermBackend::new(stdout);
+        let player = AnyPlayer::new()?;
+        Self::with_backend(backend, player)
+    }
 
-        let player = DefaultPlayer::new()?;
+    /// Creates the app with the given terminal backend and player. Tests use it with a
+    /// [TestBackend](tui::backend::TestBackend) and a [MockPlayer](crate::player::mock::MockPlayer).
+    pub(crate) fn with_backend(backend: MyBackend, player: AnyPlayer) -> Result<App<'a>> {
+        let terminal = Terminal::new(backend)?;
 
         let screen = Rc::new(RefCell::new(AppScreen::new()?));
 
@@ -75,6 +86,7 @@ impl<'a> App<'a> {
             channel,
             terminal,
             player,
+            shuffle: Shuffle::default(),
             next_render,
             next_poll_timeout,
             notification,
@@ -86,6 +98,12 @@ impl<'a> App<'a> {
 
     pub fn run(&mut self) -> Result<()> {
         self.chain_hook();
+        self.player.forward_events(self.channel.sender.clone())?;
+        if Config::global().restore_session {
+            self.restore_session()
+                .map_err(|e| self.notify_err(format!("Couldn't restore the session: {}", e)))
+                .ok();
+        }
         setup_terminal()?;
 
         self.channel.spawn_terminal_event_getter();
@@ -100,7 +118,21 @@ impl<'a> App<'a> {
                 .ok();
         }
 
+        let screen = self.screen.clone();
+        let saved = screen
+            .borrow()
+            .session(self)
+            .and_then(|session| session.save(&Session::default_path()));
+
         reset_terminal()?;
+        saved.map_err(|e| format!("Couldn't save the session: {}", e).into())
+    }
+
+    fn restore_session(&mut self) -> Result<()> {
+        if let Some(session) = Session::load(&Session::default_path())? {
+            let screen = self.screen.clone();
+            screen.borrow_mut().restore_session(self, &session)?;
+        }
         Ok(())
     }
 
@@ -2,11 +2,13 @@ pub mod confirmation_modal;
 pub mod help_modal;
 pub mod hotkey_modal;
 pub mod input_modal;
+pub mod select_modal;
 
 pub use confirmation_modal::ConfirmationModal;
 pub use help_modal::HelpModal;
 pub use hotkey_modal::HotkeyModal;
 pub use input_modal::InputModal;
+pub use select_modal::SelectModal;
 
 use tui::{layout::Rect, style::Style, Frame};
 
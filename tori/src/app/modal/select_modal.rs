@@ -0,0 +1,156 @@
+use super::{get_modal_chunk, Message, Modal};
+
+use std::borrow::Cow;
+
+use crossterm::event::KeyCode;
+use tui::{
+    layout::Alignment,
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{app::component::Mode, error::Result, events::Event};
+
+/// A modal box that asks the user to pick one item of a list
+#[derive(Debug, Default)]
+pub struct SelectModal<'t> {
+    title: Cow<'t, str>,
+    items: Vec<String>,
+    state: ListState,
+    style: Style,
+}
+
+impl<'t> SelectModal<'t> {
+    pub fn new(title: impl Into<Cow<'t, str>>, items: Vec<String>) -> Self {
+        let mut state = ListState::default();
+        state.select((!items.is_empty()).then_some(0));
+        Self {
+            title: title.into(),
+            items,
+            state,
+            style: Style::default().fg(Color::LightBlue),
+        }
+    }
+
+    /// Selects the item equal to `item`, if there's one
+    pub fn select(mut self, item: &str) -> Self {
+        if let Some(i) = self.items.iter().position(|x| x == item) {
+            self.state.select(Some(i));
+        }
+        self
+    }
+
+    fn move_selection(&mut self, x: isize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = self.state.selected().unwrap_or(0) as isize + x;
+        let i = i.clamp(0, self.items.len() as isize - 1) as usize;
+        self.state.select(Some(i));
+    }
+}
+
+impl<'t> Modal for SelectModal<'t> {
+    fn apply_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<Message> {
+        use Event::*;
+        use KeyCode::*;
+        if let Terminal(crossterm::event::Event::Key(event)) = event {
+            match event.code {
+                Up | Char('k') => self.move_selection(-1),
+                Down | Char('j') => self.move_selection(1),
+                Home => self.state.select(Some(0)),
+                End => self.move_selection(self.items.len() as isize),
+                Esc | Char('q') => return Ok(Message::Quit),
+                Enter => {
+                    return Ok(match self.state.selected() {
+                        Some(i) => Message::Commit(self.items[i].clone()),
+                        None => Message::Quit,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(Message::Nothing)
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let size = frame.size();
+        let mut chunk = get_modal_chunk(size);
+        chunk.height = (self.items.len() as u16 + 2)
+            .max(chunk.height)
+            .min(size.height.saturating_sub(6));
+        chunk.y = size.height.saturating_sub(chunk.height) / 2;
+
+        let block = Block::default()
+            .title(self.title.as_ref())
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .border_style(self.style);
+
+        let items: Vec<_> = self
+            .items
+            .iter()
+            .map(|item| ListItem::new(item.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().bg(Color::LightBlue).fg(Color::Black));
+
+        frame.render_widget(Clear, chunk);
+        frame.render_stateful_widget(list, chunk, &mut self.state);
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Insert
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{Event::Key, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
+
+    fn key_event(code: KeyCode) -> Event {
+        Event::Terminal(Key(KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        }))
+    }
+
+    #[test]
+    fn test_select_modal_lifecycle() {
+        let items = vec!["default".into(), "ALSA:pipewire".into(), "null".into()];
+        let mut modal = SelectModal::new("select", items).select("ALSA:pipewire");
+
+        assert_eq!(
+            modal.handle_event(key_event(KeyCode::Down)).ok(),
+            Some(Message::Nothing)
+        );
+        assert_eq!(
+            modal.handle_event(key_event(KeyCode::Down)).ok(),
+            Some(Message::Nothing)
+        );
+        assert_eq!(
+            modal.handle_event(key_event(KeyCode::Enter)).ok(),
+            Some(Message::Commit("null".into()))
+        );
+
+        modal.move_selection(-10);
+        assert_eq!(
+            modal.handle_event(key_event(KeyCode::Enter)).ok(),
+            Some(Message::Commit("default".into()))
+        );
+        assert_eq!(
+            modal.handle_event(key_event(KeyCode::Esc)).ok(),
+            Some(Message::Quit)
+        );
+    }
+}
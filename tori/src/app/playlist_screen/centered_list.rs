@@ -19,7 +19,6 @@ pub struct CenteredListState {
 }
 
 impl CenteredListState {
-    #[allow(dead_code)]
     pub fn selected(&self) -> Option<usize> {
         self.selected
     }
@@ -49,7 +48,6 @@ impl<'a> CenteredListItem<'a> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn style(mut self, style: Style) -> CenteredListItem<'a> {
         self.style = style;
         self
@@ -227,7 +225,7 @@ impl<'a> StatefulWidget for CenteredList<'a> {
                 width: list_area.width,
                 height: item.height() as u16,
             };
-            let item_style = self.style; //.patch(item.style);
+            let item_style = self.style.patch(item.style);
             buf.set_style(area, item_style);
 
             let is_selected = state.selected.map(|s| s == i).unwrap_or(false);
@@ -3,8 +3,13 @@ use super::{
     component::{Component, MouseHandler},
     App, Mode,
 };
-use crate::{command, error::Result, events, player::Player, widgets::Scrollbar};
-use std::{thread, time::Duration};
+use crate::{
+    command,
+    error::Result,
+    events,
+    player::{shuffle::ShuffleMode, Player},
+    widgets::Scrollbar,
+};
 use tui::{
     layout::{Alignment, Rect},
     style::{Color, Style},
@@ -13,44 +18,50 @@ use tui::{
 
 mod centered_list;
 
-/// Screen that shows the current mpv playlist. You can press '2' to access it.
+/// Screen that shows the current mpv playlist, also known as the queue. You can press '2' to access
+/// it. The selected song can be played, moved or removed from the queue.
 #[derive(Debug, Default)]
 pub struct PlaylistScreen {
     songs: Vec<String>,
-    playing: CenteredListState,
+    selected: CenteredListState,
+    playing: Option<usize>,
+    shuffle: ShuffleMode,
 }
 
 impl PlaylistScreen {
     /// See <https://mpv.io/manual/master/#command-interface-playlist>
-    pub fn update(&mut self, player: &impl Player) -> Result<&mut Self> {
+    pub fn update(&mut self, app: &App) -> Result<&mut Self> {
+        let player = &app.player;
         let n = player.playlist_count()?;
 
         self.songs = (0..n)
             .map(|i| player.playlist_track_title(i))
             .collect::<Result<_>>()?;
 
-        self.playing.select(player.playlist_position().ok());
+        self.playing = player.playlist_position().ok();
+        self.shuffle = app.shuffle.mode();
 
-        Ok(self)
-    }
+        match self.selected.selected() {
+            None => self.selected.select(self.playing),
+            Some(i) if i >= n => self.selected.select(n.checked_sub(1)),
+            Some(_) => {}
+        }
 
-    /// Waits a couple of milliseconds, then calls [update](PlaylistScreen::update). It's used
-    /// primarily by [select_next](PlaylistScreen::select_next) and
-    /// [select_prev](PlaylistScreen::select_prev) because mpv takes a while to update the playlist
-    /// properties after changing the selection.
-    pub fn update_after_delay(&self, app: &App) {
-        let sender = app.channel.sender.clone();
-        thread::spawn(move || {
-            thread::sleep(Duration::from_millis(16));
-            sender.send(events::Event::SecondTick).ok();
-        });
+        Ok(self)
     }
 
     fn handle_command(&mut self, app: &mut App, cmd: command::Command) -> Result<()> {
         use command::Command::*;
         match cmd {
-            SelectNext | NextSong => self.select_next(app),
-            SelectPrev | PrevSong => self.select_prev(app),
+            SelectNext => self.move_selection(1),
+            SelectPrev => self.move_selection(-1),
+            SwapSongDown => self.move_selected_song(app, 1)?,
+            SwapSongUp => self.move_selected_song(app, -1)?,
+            Delete => self.remove_selected_song(app)?,
+            ClearQueue => {
+                app.player.playlist_clear()?;
+                self.update(app)?;
+            }
             _ => {}
         }
         Ok(())
@@ -64,26 +75,52 @@ impl PlaylistScreen {
         use crossterm::event::{Event, KeyCode};
         if let Event::Key(key_event) = event {
             match key_event.code {
-                KeyCode::Up => self.select_prev(app),
-                KeyCode::Down => self.select_next(app),
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Down => self.move_selection(1),
+                KeyCode::Enter => self.play_selected_song(app)?,
+                KeyCode::Delete => self.remove_selected_song(app)?,
                 _ => {}
             }
         }
         Ok(())
     }
 
-    pub fn select_next(&self, app: &mut App) {
-        app.player
-            .playlist_next()
-            .unwrap_or_else(|_| app.notify_err("No next song"));
-        self.update_after_delay(app);
+    fn move_selection(&mut self, x: isize) {
+        if self.songs.is_empty() {
+            return;
+        }
+        let i = self.selected.selected().unwrap_or(0) as isize + x;
+        let i = i.clamp(0, self.songs.len() as isize - 1) as usize;
+        self.selected.select(Some(i));
     }
 
-    pub fn select_prev(&self, app: &mut App) {
-        app.player
-            .playlist_previous()
-            .unwrap_or_else(|_| app.notify_err("No previous song"));
-        self.update_after_delay(app);
+    fn play_selected_song(&mut self, app: &mut App) -> Result<()> {
+        if let Some(i) = self.selected.selected() {
+            app.player.playlist_play_index(i)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the selected song `x` positions down the queue, or up if `x` is negative
+    fn move_selected_song(&mut self, app: &mut App, x: isize) -> Result<()> {
+        let Some(from) = self.selected.selected() else {
+            return Ok(());
+        };
+        let to = (from as isize + x).clamp(0, self.songs.len() as isize - 1) as usize;
+        if from != to {
+            app.player.playlist_move(from, to)?;
+            self.selected.select(Some(to));
+            self.update(app)?;
+        }
+        Ok(())
+    }
+
+    fn remove_selected_song(&mut self, app: &mut App) -> Result<()> {
+        if let Some(i) = self.selected.selected() {
+            app.player.playlist_remove(i)?;
+            self.update(app)?;
+        }
+        Ok(())
     }
 }
 
@@ -95,8 +132,12 @@ impl Component for PlaylistScreen {
     }
 
     fn render(&mut self, frame: &mut tui::Frame, chunk: Rect, (): ()) {
+        let title = match self.shuffle {
+            ShuffleMode::Off => " Playlist ".to_string(),
+            mode => format!(" Playlist ({}) ", mode),
+        };
         let block = Block::default()
-            .title(" Playlist ")
+            .title(title)
             .title_alignment(Alignment::Center)
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
@@ -105,7 +146,15 @@ impl Component for PlaylistScreen {
         let items: Vec<_> = self
             .songs
             .iter()
-            .map(|x| CenteredListItem::new(x.as_str()))
+            .enumerate()
+            .map(|(i, x)| {
+                let item = CenteredListItem::new(x.as_str());
+                if Some(i) == self.playing {
+                    item.style(Style::default().fg(Color::Yellow))
+                } else {
+                    item
+                }
+            })
             .collect();
         let list = CenteredList::new(items)
             .block(block)
@@ -113,10 +162,10 @@ impl Component for PlaylistScreen {
             .highlight_symbol("›")
             .highlight_symbol_right("‹");
 
-        frame.render_stateful_widget(list, chunk, &mut self.playing);
+        frame.render_stateful_widget(list, chunk, &mut self.selected);
 
         if self.songs.len() > chunk.height as usize - 2 {
-            if let Some(index) = self.playing.selected() {
+            if let Some(index) = self.selected.selected() {
                 let scrollbar = Scrollbar::new(index as u16, self.songs.len() as u16)
                     .with_style(Style::default().fg(Color::Red));
                 frame.render_widget(scrollbar, chunk);
@@ -129,8 +178,13 @@ impl Component for PlaylistScreen {
         match event {
             Command(cmd) => self.handle_command(app, cmd)?,
             Terminal(event) => self.handle_terminal_event(app, event)?,
-            SecondTick => {
-                self.update(&app.player)?;
+            TrackChanged | EndOfFile | QueueChanged => {
+                // The selection follows the song being played, unless it was moved to another one
+                let following = self.selected.selected() == self.playing;
+                self.update(app)?;
+                if following {
+                    self.selected.select(self.playing);
+                }
             }
             _ => {}
         }
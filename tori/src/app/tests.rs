@@ -0,0 +1,931 @@
+//! Tests of the whole app, without a terminal or an audio device. The app renders to a
+//! [TestBackend] and plays songs on a [MockPlayer], and the playlists live in a temporary
+//! `playlists_dir`.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, Once},
+    time,
+};
+
+use crossterm::event::{Event::Key, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
+use tui::backend::TestBackend;
+
+use super::App;
+use crate::{
+    command::Command,
+    config::Config,
+    events::Event,
+    m3u::playlist_management,
+    player::{
+        mock::{Call, MockPlayer},
+        shuffle::ShuffleMode,
+        AnyPlayer, Chapter, Player, RepeatMode,
+    },
+    session::Session,
+};
+
+/// Every test uses the same `playlists_dir`, because the config is global
+static PLAYLISTS_DIR: Mutex<()> = Mutex::new(());
+
+fn playlists_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("tori-tests-{}", std::process::id()))
+}
+
+struct Harness<'a> {
+    app: App<'a>,
+    _dir: MutexGuard<'static, ()>,
+}
+
+impl<'a> Harness<'a> {
+    /// Creates the app with the given playlists, each one a list of (title, path)
+    fn new(playlists: &[(&str, &[(&str, &str)])]) -> Self {
+        static CONFIG: Once = Once::new();
+        CONFIG.call_once(|| {
+            Config::set_global(Config {
+                playlists_dir: playlists_dir().to_string_lossy().into_owned(),
+                ..Default::default()
+            });
+        });
+
+        // A failed test poisons the lock, but it doesn't matter since the directory is recreated
+        let dir = PLAYLISTS_DIR.lock().unwrap_or_else(|e| e.into_inner());
+        fs::remove_dir_all(playlists_dir()).ok();
+        fs::create_dir_all(playlists_dir()).unwrap();
+
+        for (name, songs) in playlists {
+            let mut contents = String::from("#EXTM3U\n");
+            for (title, path) in songs.iter() {
+                contents += &format!("#EXTINF:60,{}\n{}\n", title, path);
+            }
+            fs::write(Config::playlist_path(name), contents).unwrap();
+        }
+
+        let backend = TestBackend::new(100, 30);
+        let player = AnyPlayer::Mock(MockPlayer::default());
+        let mut harness = Self {
+            app: App::with_backend(backend, player).unwrap(),
+            _dir: dir,
+        };
+        harness.settle();
+        harness
+    }
+
+    /// Handles an event like the app would, and then the events it caused
+    fn send(&mut self, event: Event) {
+        let event = self.app.transform_event(event);
+        self.app.handle_event(event).unwrap();
+        self.settle();
+    }
+
+    fn command(&mut self, cmd: Command) {
+        self.send(Event::Command(cmd));
+    }
+
+    fn key(&mut self, code: KeyCode) {
+        self.send(Event::Terminal(Key(KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        })));
+    }
+
+    fn type_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.key(KeyCode::Char(c));
+        }
+    }
+
+    fn erase(&mut self, n: usize) {
+        for _ in 0..n {
+            self.key(KeyCode::Backspace);
+        }
+    }
+
+    /// Handles the events that are waiting in the channel, like [Event::ChangedPlaylist]
+    fn settle(&mut self) {
+        while let Ok(event) = self.app.channel.receiver.try_recv() {
+            let event = self.app.transform_event(event);
+            self.app.handle_event(event).unwrap();
+        }
+    }
+
+    /// Renders the app and returns the text on the screen, one line per row
+    fn screen(&mut self) -> String {
+        self.app.next_render = time::Instant::now();
+        self.app.render().unwrap();
+
+        let buffer = self.app.terminal.backend().buffer();
+        let width = buffer.area.width as usize;
+        buffer
+            .content
+            .chunks(width)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn player(&self) -> &MockPlayer {
+        match &self.app.player {
+            AnyPlayer::Mock(player) => player,
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    fn player_mut(&mut self) -> &mut MockPlayer {
+        match &mut self.app.player {
+            AnyPlayer::Mock(player) => player,
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    fn playlist_file(&self, name: &str) -> String {
+        fs::read_to_string(Config::playlist_path(name)).unwrap()
+    }
+}
+
+const ROCK: (&str, &[(&str, &str)]) = (
+    "rock",
+    &[
+        ("Paranoid", "/music/paranoid.mp3"),
+        ("Back in Black", "/music/back_in_black.mp3"),
+        ("Smoke on the Water", "/music/smoke.mp3"),
+    ],
+);
+
+const JAZZ: (&str, &[(&str, &str)]) = ("jazz", &[("So What", "/music/so_what.flac")]);
+
+#[test]
+fn test_shows_playlists_and_songs() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    let screen = h.screen();
+    assert!(screen.contains("jazz"));
+    assert!(screen.contains("rock"));
+    // Playlists are sorted, so jazz is the one that's selected
+    assert!(screen.contains("So What"));
+    assert!(!screen.contains("Paranoid"));
+
+    h.command(Command::SelectNext);
+    let screen = h.screen();
+    assert!(screen.contains("Paranoid"));
+    assert!(screen.contains("Smoke on the Water"));
+    assert!(!screen.contains("So What"));
+}
+
+#[test]
+fn test_play_and_queue() {
+    let mut h = Harness::new(&[ROCK]);
+    h.command(Command::SelectRight);
+
+    h.key(KeyCode::Enter);
+    h.command(Command::SelectNext);
+    h.command(Command::QueueSong);
+    assert_eq!(
+        h.player().calls,
+        [
+            Call::Play("/music/paranoid.mp3".into()),
+            Call::Queue("/music/back_in_black.mp3".into()),
+        ]
+    );
+
+    h.command(Command::QueueShown);
+    assert_eq!(h.player().playlist.len(), 5);
+    assert_eq!(h.player().calls[4], Call::Queue("/music/smoke.mp3".into()));
+
+    // The playlist screen shows the queue, and follows the player when the track changes
+    h.key(KeyCode::Char('2'));
+    let screen = h.screen();
+    assert!(screen.contains("paranoid"));
+    assert!(screen.contains("back_in_black"));
+    h.command(Command::NextSong);
+    h.send(Event::TrackChanged);
+    assert_eq!(h.player().position, Some(1));
+    assert!(h.screen().contains("back_in_black"));
+
+    // Songs queued while it's shown appear once the player says the queue changed
+    h.player_mut().queue("/music/so_what.flac").unwrap();
+    assert!(!h.screen().contains("so_what"));
+    h.send(Event::QueueChanged);
+    assert!(h.screen().contains("so_what"));
+}
+
+#[test]
+fn test_delete_song() {
+    let mut h = Harness::new(&[ROCK]);
+    h.command(Command::SelectRight);
+    h.command(Command::SelectNext);
+
+    // Cancelling doesn't delete anything
+    h.command(Command::Delete);
+    h.key(KeyCode::Esc);
+    assert!(h.playlist_file("rock").contains("Back in Black"));
+
+    h.command(Command::Delete);
+    assert!(h.screen().contains("Back in Black"));
+    h.key(KeyCode::Enter);
+
+    let file = h.playlist_file("rock");
+    assert!(!file.contains("Back in Black"));
+    assert!(file.contains("Paranoid"));
+    assert!(file.contains("Smoke on the Water"));
+    assert!(!h.screen().contains("Back in Black"));
+}
+
+#[test]
+fn test_rename_playlist_and_song() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    h.command(Command::SelectNext);
+
+    h.command(Command::Rename);
+    h.type_str(" classics");
+    h.key(KeyCode::Enter);
+    assert!(!Config::playlist_path("rock").exists());
+    assert!(h.playlist_file("rock classics").contains("Paranoid"));
+    assert!(h.screen().contains("rock classics"));
+
+    h.command(Command::SelectRight);
+    h.command(Command::Rename);
+    h.erase("Paranoid".len());
+    h.type_str("War Pigs");
+    h.key(KeyCode::Enter);
+    assert!(h.playlist_file("rock classics").contains("War Pigs"));
+    assert!(h.screen().contains("War Pigs"));
+
+    // Renaming to an existing playlist is refused
+    h.command(Command::SelectLeft);
+    h.command(Command::SelectPrev);
+    h.command(Command::Rename);
+    h.erase("jazz".len());
+    h.type_str("rock classics");
+    h.key(KeyCode::Enter);
+    assert!(Config::playlist_path("jazz").exists());
+    assert!(h.screen().contains("already exists"));
+}
+
+#[test]
+fn test_writes_keep_a_backup() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    let before = h.playlist_file("rock");
+    h.command(Command::SelectNext);
+    h.command(Command::SelectRight);
+    h.command(Command::Delete);
+    h.key(KeyCode::Enter);
+
+    let backup = playlists_dir().join("rock.m3u8.bak");
+    assert_eq!(fs::read_to_string(&backup).unwrap(), before);
+    assert!(!h.playlist_file("rock").contains("Paranoid"));
+
+    // No temporary files are left behind
+    let files: Vec<_> = fs::read_dir(playlists_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert!(files.iter().all(|f| !f.ends_with(".tmp")), "{:?}", files);
+
+    // A deleted playlist can still be recovered from its backup
+    let after = h.playlist_file("rock");
+    h.command(Command::SelectLeft);
+    h.command(Command::Delete);
+    h.key(KeyCode::Enter);
+    assert!(!Config::playlist_path("rock").exists());
+    assert_eq!(fs::read_to_string(&backup).unwrap(), after);
+    assert!(!h.screen().contains("rock"));
+}
+
+#[test]
+fn test_concurrent_adds() {
+    let mut h = Harness::new(&[JAZZ]);
+    let songs: Vec<_> = (0..8)
+        .map(|i| playlists_dir().join(format!("song{}", i)))
+        .collect();
+    for song in &songs {
+        fs::write(song, "").unwrap();
+        playlist_management::add_song(&mut h.app, "jazz", song.to_string_lossy().into_owned());
+    }
+
+    // Every add reads and saves the playlist in its own thread, and none of them is lost
+    let mut added = 0;
+    while added < songs.len() {
+        let event = h
+            .app
+            .channel
+            .receiver
+            .recv_timeout(time::Duration::from_secs(10));
+        if let Event::SongAdded { .. } = event.unwrap() {
+            added += 1;
+        }
+    }
+    let file = h.playlist_file("jazz");
+    assert!(file.contains("#EXTINF:60,So What\n"));
+    for song in &songs {
+        assert!(file.contains(&*song.to_string_lossy()), "{}", file);
+    }
+
+    // Adding songs to a playlist that was deleted meanwhile doesn't bring it back
+    playlist_management::delete_playlist("jazz").unwrap();
+    let song = songs[0].to_string_lossy().into_owned();
+    playlist_management::add_song(&mut h.app, "jazz", song);
+    let event = h
+        .app
+        .channel
+        .receiver
+        .recv_timeout(time::Duration::from_secs(10));
+    assert!(matches!(event.unwrap(), Event::AddSongFailed(_)));
+    assert!(!Config::playlist_path("jazz").exists());
+}
+
+#[test]
+fn test_moves_are_saved_once() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    let before = h.playlist_file("rock");
+    let backup = playlists_dir().join("rock.m3u8.bak");
+    h.command(Command::SelectNext);
+    h.command(Command::SelectRight);
+    h.command(Command::SwapSongDown);
+    h.command(Command::SwapSongDown);
+    h.command(Command::SwapSongUp);
+    h.command(Command::SwapSongDown);
+    assert_eq!(h.playlist_file("rock"), before);
+    assert!(!backup.exists());
+
+    // The playlist is written when the songs pane loses focus. Since the backup is the version
+    // before the last write, it would be one of the moves if every move had been written.
+    h.command(Command::SelectLeft);
+    assert_eq!(fs::read_to_string(&backup).unwrap(), before);
+    assert_eq!(
+        h.playlist_file("rock"),
+        "#EXTM3U\n\
+         #EXTINF:60,Back in Black\n\
+         /music/back_in_black.mp3\n\
+         #EXTINF:60,Smoke on the Water\n\
+         /music/smoke.mp3\n\
+         #EXTINF:60,Paranoid\n\
+         /music/paranoid.mp3\n"
+    );
+
+    // And when quitting
+    let before = h.playlist_file("rock");
+    h.command(Command::SelectRight);
+    h.command(Command::SwapSongUp);
+    h.command(Command::SwapSongUp);
+    assert_eq!(h.playlist_file("rock"), before);
+    h.command(Command::Quit);
+    assert_eq!(fs::read_to_string(&backup).unwrap(), before);
+    assert!(h
+        .playlist_file("rock")
+        .starts_with("#EXTM3U\n#EXTINF:60,Paranoid\n"));
+}
+
+#[test]
+fn test_moves_keep_added_songs() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    h.command(Command::SelectNext);
+    h.command(Command::SelectRight);
+    h.command(Command::SwapSongDown);
+
+    // A song is added while the move isn't saved yet
+    let song = playlists_dir().join("song");
+    fs::write(&song, "").unwrap();
+    playlist_management::add_song(&mut h.app, "rock", song.to_string_lossy().into_owned());
+    let event = h
+        .app
+        .channel
+        .receiver
+        .recv_timeout(time::Duration::from_secs(10));
+    let event = event.unwrap();
+    assert!(matches!(event, Event::SongAdded { .. }));
+    h.send(event);
+
+    let file = h.playlist_file("rock");
+    assert!(
+        file.starts_with(
+            "#EXTM3U\n\
+             #EXTINF:60,Back in Black\n\
+             /music/back_in_black.mp3\n\
+             #EXTINF:60,Paranoid\n"
+        ),
+        "{}",
+        file
+    );
+    assert!(file.ends_with(&format!("{}\n", song.display())), "{}", file);
+
+    // A file changed by another program isn't overwritten, and it's shown as it is now
+    let changed = "#EXTM3U\n#EXTINF:60,So What\n/music/so_what.flac\n";
+    h.command(Command::SwapSongDown);
+    fs::write(Config::playlist_path("rock"), changed).unwrap();
+    let left = Event::Command(Command::SelectLeft);
+    assert!(h.app.handle_event(left).is_err());
+    assert_eq!(h.playlist_file("rock"), changed);
+    assert!(!h.screen().contains("Paranoid"));
+}
+
+#[test]
+fn test_edits_keep_unknown_lines() {
+    let mut h = Harness::new(&[ROCK]);
+    fs::write(
+        Config::playlist_path("rock"),
+        "#EXTM3U\n\
+         #PLAYLIST:Rock\n\
+         #EXTINF:60,Paranoid\n\
+         #EXTVLCOPT:start-time=5\n\
+         /music/paranoid.mp3\n\
+         # live version\n\
+         #EXTINF:60,Back in Black\n\
+         /music/back_in_black.mp3\n\
+         #EXTINF:60,Smoke on the Water\n\
+         /music/smoke.mp3\n",
+    )
+    .unwrap();
+    // Like after editing it in the editor
+    h.send(Event::ChangedPlaylist);
+
+    h.command(Command::SelectRight);
+    h.command(Command::Rename);
+    h.erase("Paranoid".len());
+    h.type_str("War Pigs");
+    h.key(KeyCode::Enter);
+    h.command(Command::SelectNext);
+    h.command(Command::SwapSongUp);
+    h.command(Command::SelectLeft);
+
+    assert_eq!(
+        h.playlist_file("rock"),
+        "#EXTM3U\n\
+         #PLAYLIST:Rock\n\
+         # live version\n\
+         #EXTINF:60,Back in Black\n\
+         /music/back_in_black.mp3\n\
+         #EXTINF:60,War Pigs\n\
+         #EXTVLCOPT:start-time=5\n\
+         /music/paranoid.mp3\n\
+         #EXTINF:60,Smoke on the Water\n\
+         /music/smoke.mp3\n"
+    );
+}
+
+#[test]
+fn test_relative_paths() {
+    let mut h = Harness::new(&[(
+        "mixed",
+        &[
+            ("Paranoid", "metal/paranoid.mp3"),
+            ("So What", "/music/so_what.flac"),
+            ("Radio", "https://example.com/radio.mp3"),
+        ],
+    )]);
+    h.command(Command::SelectRight);
+    h.command(Command::QueueShown);
+
+    // Relative paths are relative to the playlist, not to the working directory
+    let paranoid = playlists_dir().join("metal/paranoid.mp3");
+    assert_eq!(
+        h.player().playlist,
+        [
+            paranoid.to_string_lossy(),
+            "/music/so_what.flac".into(),
+            "https://example.com/radio.mp3".into()
+        ]
+    );
+
+    // They're written back as they were
+    h.command(Command::Rename);
+    h.erase("Paranoid".len());
+    h.type_str("War Pigs");
+    h.key(KeyCode::Enter);
+    assert!(h
+        .playlist_file("mixed")
+        .contains("#EXTINF:60,War Pigs\nmetal/paranoid.mp3\n"));
+}
+
+#[test]
+fn test_import_and_export_playlists() {
+    let mut h = Harness::new(&[ROCK]);
+    let cue = playlists_dir().join("kind_of_blue.cue");
+    fs::write(
+        &cue,
+        "PERFORMER \"Miles Davis\"\n\
+         FILE \"kind_of_blue.flac\" WAVE\n\
+         TRACK 01 AUDIO\n\
+         TITLE \"So What\"\n\
+         INDEX 01 00:00:00\n\
+         TRACK 02 AUDIO\n\
+         TITLE \"Freddie Freeloader\"\n\
+         INDEX 01 09:22:30\n",
+    )
+    .unwrap();
+
+    h.command(Command::ImportPlaylist);
+    h.type_str(cue.to_str().unwrap());
+    h.key(KeyCode::Enter);
+
+    // The CUE sheet itself isn't a playlist, so it's only in the notification, and the imported
+    // playlist is selected
+    assert_eq!(h.screen().matches("kind_of_blue.cue").count(), 1);
+    assert!(h.screen().contains("Freddie Freeloader"));
+    assert!(h.playlist_file("kind_of_blue").contains(
+        "#EXTINF:562.4,So What\n\
+         #EXTART:Miles Davis\n\
+         #EXTVLCOPT:start-time=0\n\
+         #EXTVLCOPT:stop-time=562.4\n"
+    ));
+
+    // Every track plays its section of the file
+    let flac = playlists_dir().join("kind_of_blue.flac");
+    let flac = flac.to_str().unwrap();
+    h.command(Command::SelectRight);
+    h.command(Command::SelectNext);
+    h.key(KeyCode::Enter);
+    h.command(Command::SelectPrev);
+    h.command(Command::PlayNext);
+    assert_eq!(
+        h.player().calls,
+        [
+            Call::PlaySection(flac.into(), 562.4, None),
+            Call::QueueSection(flac.into(), 0.0, Some(562.4)),
+        ]
+    );
+
+    h.command(Command::SelectLeft);
+    h.command(Command::ExportPlaylist);
+    h.erase("kind_of_blue.xspf".len());
+    let pls = playlists_dir().join("exported.pls");
+    h.type_str(pls.to_str().unwrap());
+    h.key(KeyCode::Enter);
+    assert_eq!(
+        fs::read_to_string(pls).unwrap(),
+        format!(
+            "[playlist]\n\
+             File1={0}\nTitle1=So What\nLength1=562\n\
+             File2={0}\nTitle2=Freddie Freeloader\nLength2=0\n\
+             NumberOfEntries=2\nVersion=2\n",
+            flac
+        )
+    );
+}
+
+#[test]
+fn test_artist_and_album_columns() {
+    let mut h = Harness::new(&[JAZZ, ROCK]);
+    fs::write(
+        Config::playlist_path("rock"),
+        "#EXTM3U\n\
+         #EXTINF:60 tvg-logo=\"sabbath.png\",Paranoid\n\
+         #EXTART:Black Sabbath\n\
+         #EXTALB:Paranoid\n\
+         /music/paranoid.mp3\n\
+         #EXTINF:60,Back in Black\n\
+         #EXTART:AC/DC\n\
+         /music/back_in_black.mp3\n",
+    )
+    .unwrap();
+
+    // Selecting the playlist loads it again
+    h.command(Command::SelectNext);
+    h.command(Command::SelectRight);
+    assert!(h.screen().contains("Black Sabbath"));
+    assert!(h.screen().contains("AC/DC"));
+
+    h.command(Command::Search);
+    h.type_str("ac/dc");
+    assert!(h.screen().contains("Back in Black"));
+    assert!(!h.screen().contains("Black Sabbath"));
+    h.key(KeyCode::Esc);
+
+    // Renaming a song keeps its attributes and the order of its lines
+    h.command(Command::SelectPrev);
+    h.command(Command::Rename);
+    h.erase("Paranoid".len());
+    h.type_str("War Pigs");
+    h.key(KeyCode::Enter);
+    assert_eq!(
+        h.playlist_file("rock"),
+        "#EXTM3U\n\
+         #EXTINF:60 tvg-logo=\"sabbath.png\",War Pigs\n\
+         #EXTART:Black Sabbath\n\
+         #EXTALB:Paranoid\n\
+         /music/paranoid.mp3\n\
+         #EXTINF:60,Back in Black\n\
+         #EXTART:AC/DC\n\
+         /music/back_in_black.mp3\n"
+    );
+}
+
+#[test]
+fn test_repeat_modes() {
+    let mut h = Harness::new(&[ROCK]);
+    h.command(Command::SelectRight);
+    h.key(KeyCode::Enter);
+    assert!(!h.screen().contains('🔁'));
+
+    h.command(Command::CycleRepeat);
+    assert_eq!(h.player().repeat, RepeatMode::All);
+    assert!(h.screen().contains('🔁'));
+
+    h.command(Command::CycleRepeat);
+    assert_eq!(h.player().repeat, RepeatMode::One);
+    let screen = h.screen();
+    assert!(screen.contains('🔂'));
+    assert!(!screen.contains('🔁'));
+
+    h.command(Command::CycleRepeat);
+    assert_eq!(h.player().repeat, RepeatMode::Off);
+
+    // ToggleLoop only repeats the current song
+    h.command(Command::ToggleLoop);
+    assert_eq!(h.player().repeat, RepeatMode::One);
+    h.command(Command::ToggleLoop);
+    assert_eq!(h.player().repeat, RepeatMode::Off);
+}
+
+#[test]
+fn test_shuffle_and_unshuffle() {
+    let songs: Vec<(String, String)> = (0..20)
+        .map(|i| (format!("Song {}", i), format!("/music/{}/{}.mp3", i % 4, i)))
+        .collect();
+    let songs: Vec<(&str, &str)> = songs
+        .iter()
+        .map(|(title, path)| (title.as_str(), path.as_str()))
+        .collect();
+    let mut h = Harness::new(&[("long", &songs)]);
+    h.command(Command::SelectRight);
+    h.command(Command::QueueShown);
+    let original = h.player().playlist.clone();
+    h.command(Command::NextSong);
+    h.command(Command::NextSong);
+
+    h.command(Command::Shuffle);
+    assert_ne!(h.player().playlist, original);
+    assert_eq!(h.player().position, Some(0));
+    assert_eq!(h.player().playlist[0], "/music/2/2.mp3");
+    assert!(h.screen().contains('🔀'));
+
+    // Shuffling by album starts from the original order, not the shuffled one
+    h.command(Command::CycleShuffle);
+    assert_eq!(
+        h.player().playlist[..5],
+        original[2..20]
+            .iter()
+            .step_by(4)
+            .cloned()
+            .collect::<Vec<_>>()
+    );
+
+    h.command(Command::Shuffle);
+    assert_eq!(h.player().playlist, original);
+    assert_eq!(h.player().position, Some(2));
+    assert!(!h.screen().contains('🔀'));
+}
+
+#[test]
+fn test_edit_queue() {
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    h.command(Command::SelectRight);
+    h.command(Command::QueueShown);
+    h.command(Command::SelectLeft);
+    h.command(Command::SelectNext);
+    h.command(Command::SelectRight);
+    h.command(Command::QueueShown);
+    let queue = |h: &Harness| {
+        h.player()
+            .playlist
+            .iter()
+            .map(|path| path.trim_start_matches("/music/"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(
+        queue(&h),
+        "so_what.flac paranoid.mp3 back_in_black.mp3 smoke.mp3"
+    );
+
+    // The selection starts at the song being played
+    h.key(KeyCode::Char('2'));
+    h.command(Command::SelectNext);
+    h.command(Command::SwapSongDown);
+    h.command(Command::SwapSongDown);
+    assert_eq!(
+        queue(&h),
+        "so_what.flac back_in_black.mp3 smoke.mp3 paranoid.mp3"
+    );
+    h.command(Command::SwapSongUp);
+    assert_eq!(
+        queue(&h),
+        "so_what.flac back_in_black.mp3 paranoid.mp3 smoke.mp3"
+    );
+
+    h.command(Command::Delete);
+    assert_eq!(queue(&h), "so_what.flac back_in_black.mp3 smoke.mp3");
+    assert!(!h.screen().contains("paranoid"));
+
+    h.command(Command::SelectPrev);
+    h.key(KeyCode::Enter);
+    assert_eq!(h.player().position, Some(1));
+
+    h.command(Command::ClearQueue);
+    assert_eq!(queue(&h), "back_in_black.mp3");
+    assert_eq!(h.player().position, Some(0));
+    assert_eq!(
+        h.player().calls[h.player().calls.len() - 2..],
+        [Call::PlaylistPlayIndex(1), Call::PlaylistClear]
+    );
+}
+
+#[test]
+fn test_play_next() {
+    let mut h = Harness::new(&[ROCK]);
+    h.command(Command::SelectRight);
+    h.key(KeyCode::Enter);
+    h.command(Command::SelectNext);
+    h.command(Command::SelectNext);
+    h.command(Command::QueueSong);
+
+    h.command(Command::SelectPrev);
+    h.command(Command::PlayNext);
+    assert!(h.screen().contains("\"Back in Black\" will play next"));
+
+    // With a filter, every shown song is played next, in order
+    h.type_str("/o");
+    h.key(KeyCode::Enter);
+    h.command(Command::PlayNext);
+    assert!(h.screen().contains("2 songs will play next"));
+
+    let queue: Vec<_> = h
+        .player()
+        .playlist
+        .iter()
+        .map(|path| path.trim_start_matches("/music/"))
+        .collect();
+    assert_eq!(
+        queue,
+        [
+            "paranoid.mp3",
+            "paranoid.mp3",
+            "smoke.mp3",
+            "back_in_black.mp3",
+            "smoke.mp3"
+        ]
+    );
+}
+
+#[test]
+fn test_save_and_restore_session() {
+    let session = {
+        let mut h = Harness::new(&[ROCK, JAZZ]);
+        h.command(Command::SelectNext);
+        h.command(Command::SelectRight);
+        h.key(KeyCode::Enter);
+        h.command(Command::SelectNext);
+        h.command(Command::QueueSong);
+        h.command(Command::VolumeDown);
+        h.command(Command::CycleRepeat);
+        h.command(Command::Shuffle);
+
+        let screen = h.app.screen.clone();
+        let session = screen.borrow().session(&h.app);
+        session.unwrap()
+    };
+    assert_eq!(session.playlist.as_deref(), Some("rock"));
+    assert_eq!(session.song, Some(1));
+    assert_eq!(session.position, Some(0));
+    assert_eq!(session.queue.len(), 2);
+    assert_eq!(session.shuffle, ShuffleMode::Songs);
+    assert_eq!(session.unshuffled.len(), 2);
+
+    let mut h = Harness::new(&[ROCK, JAZZ]);
+    let screen = h.app.screen.clone();
+    screen
+        .borrow_mut()
+        .restore_session(&mut h.app, &session)
+        .unwrap();
+    assert_eq!(
+        h.player().playlist,
+        ["/music/paranoid.mp3", "/music/back_in_black.mp3"]
+    );
+    assert_eq!(h.player().position, Some(0));
+    assert!(h.player().paused);
+    assert_eq!(h.player().repeat, RepeatMode::All);
+    assert_eq!(screen.borrow().session(&h.app).unwrap(), session);
+    assert!(h.screen().contains("Smoke on the Water"));
+    assert!(h.screen().contains('🔀'));
+
+    // The restored queue can still be unshuffled
+    h.command(Command::Shuffle);
+    assert_eq!(h.app.shuffle.mode(), ShuffleMode::Off);
+    assert_eq!(h.player().playlist, session.unshuffled);
+}
+
+#[test]
+fn test_session_keeps_sections() {
+    let flac = "/music/kind_of_blue.flac";
+    let session = {
+        let mut h = Harness::new(&[ROCK]);
+        let player = h.player_mut();
+        player.queue_section(flac, 0.0, Some(562.4)).unwrap();
+        player.queue_section(flac, 562.4, None).unwrap();
+        player.queue("/music/paranoid.mp3").unwrap();
+        player.playlist_play_index(1).unwrap();
+        player.time_pos = 600.0;
+
+        let screen = h.app.screen.clone();
+        let session = screen.borrow().session(&h.app);
+        session.unwrap()
+    };
+    assert_eq!(session.queue[0].end, Some(562.4));
+    assert_eq!(session.queue[1].start, Some(562.4));
+    let yaml = serde_yaml::to_string(&session).unwrap();
+    assert_eq!(serde_yaml::from_str::<Session>(&yaml).unwrap(), session);
+
+    // The current song starts where it was left, and plays to the end of its section after that
+    let mut h = Harness::new(&[ROCK]);
+    let screen = h.app.screen.clone();
+    screen
+        .borrow_mut()
+        .restore_session(&mut h.app, &session)
+        .unwrap();
+    assert_eq!(
+        h.player().calls[1..4],
+        [
+            Call::QueueSection(flac.into(), 0.0, Some(562.4)),
+            Call::QueueSectionAt(flac.into(), 562.4, None, 600.0),
+            Call::Queue("/music/paranoid.mp3".into()),
+        ]
+    );
+    h.player_mut().time_pos = 600.0;
+    assert_eq!(screen.borrow().session(&h.app).unwrap(), session);
+}
+
+#[test]
+fn test_speed() {
+    let mut h = Harness::new(&[ROCK]);
+    h.key(KeyCode::Char(']'));
+    h.key(KeyCode::Char(']'));
+    assert_eq!(h.player().speed, 1.2);
+    assert!(h.screen().contains("1.2x"));
+
+    h.key(KeyCode::Char('='));
+    assert_eq!(h.player().speed, 1.0);
+    assert!(!h.screen().contains("1x"));
+
+    for _ in 0..10 {
+        h.command(Command::SpeedDown);
+    }
+    assert_eq!(h.player().speed, 0.3);
+}
+
+#[test]
+fn test_chapters_and_ab_loop() {
+    let mut h = Harness::new(&[ROCK]);
+    h.command(Command::SelectChapter);
+    assert!(h.screen().contains("This song has no chapters"));
+
+    h.player_mut().chapters = ["Intro", "Verse", "Solo"]
+        .iter()
+        .zip([0.0, 45.0, 130.0])
+        .map(|(title, time)| Chapter {
+            title: title.to_string(),
+            time,
+        })
+        .collect();
+    h.command(Command::NextChapter);
+    h.command(Command::NextChapter);
+    assert_eq!(h.player().chapter, Some(1));
+    h.command(Command::PrevChapter);
+    h.command(Command::PrevChapter);
+    assert!(h.screen().contains("No previous chapter"));
+
+    h.command(Command::SelectChapter);
+    assert!(h.screen().contains("02:10 Solo"));
+    h.key(KeyCode::Down);
+    h.key(KeyCode::Down);
+    h.key(KeyCode::Enter);
+    assert_eq!(h.player().chapter, Some(2));
+
+    h.player_mut().time_pos = 130.25;
+    h.command(Command::AbLoop);
+    assert!(h.screen().contains("[A-]"));
+
+    // B has to be after A
+    h.command(Command::AbLoop);
+    h.player_mut().time_pos = 130.0;
+    h.command(Command::AbLoop);
+    assert!(h.screen().contains("[A-]"));
+    assert!(h.screen().contains("has to be after the A point"));
+
+    h.player_mut().time_pos = 142.5;
+    h.command(Command::AbLoop);
+    assert!(h.screen().contains("[A-B]"));
+    h.command(Command::AbLoop);
+    assert!(!h.screen().contains("[A-"));
+    assert_eq!(
+        h.player().calls[h.player().calls.len() - 3..],
+        [
+            Call::SetAbLoop(Some(130.25), None),
+            Call::SetAbLoop(Some(130.25), Some(142.5)),
+            Call::SetAbLoop(None, None),
+        ]
+    );
+}
@@ -25,7 +25,7 @@ pub enum Command {
     /// Rename selected song or playlist
     Rename,
 
-    /// Delete selected song or playlist
+    /// Delete selected song or playlist, or remove the selected song from the queue
     Delete,
 
     /// Swap the selected song with the one below it
@@ -34,7 +34,7 @@ pub enum Command {
     /// Swap the selected song with the one above it
     SwapSongUp,
 
-    /// Shuffle current playlist
+    /// Shuffle the queue, or put it back in its original order if it's shuffled already
     Shuffle,
 
     /// Select next item (like a song or playlist)
@@ -58,6 +58,10 @@ pub enum Command {
     /// Add all shown songs to the queue
     QueueShown,
 
+    /// Play the selected song right after the current one. If the songs are filtered, all the
+    /// shown songs are played next.
+    PlayNext,
+
     /// Queries the user for a song to play, without adding it to a playlist
     PlayFromModal,
 
@@ -67,6 +71,48 @@ pub enum Command {
     /// Filter/search the selected pane (playlists or songs).
     /// The same as pressing '/'
     Search,
+
+    /// Choose the audio device the player outputs to
+    SelectOutputDevice,
+
+    /// Cycle through the repeat modes: off, repeat the playlist and repeat the current song.
+    /// ToggleLoop only switches between off and repeating the current song.
+    CycleRepeat,
+
+    /// Cycle through the shuffle modes: off, shuffle the songs, shuffle the albums and shuffle the
+    /// songs avoiding the recently played ones
+    CycleShuffle,
+
+    /// Remove every song from the queue, except the one being played
+    ClearQueue,
+
+    /// Play faster, without changing the pitch
+    SpeedUp,
+
+    /// Play slower, without changing the pitch
+    SpeedDown,
+
+    /// Play at the normal speed
+    SpeedReset,
+
+    /// Jump to the next chapter of the song
+    NextChapter,
+
+    /// Jump to the previous chapter of the song
+    PrevChapter,
+
+    /// Choose a chapter of the song to jump to
+    SelectChapter,
+
+    /// Set the start of the A/B loop at the current position, then its end, and then clear it.
+    /// While both are set, the section between them is played over and over.
+    AbLoop,
+
+    /// Convert a .m3u, .pls, .xspf or .cue playlist into a new playlist
+    ImportPlaylist,
+
+    /// Write the selected playlist to a .m3u8, .pls, .xspf or .cue file
+    ExportPlaylist,
 }
 
 #[cfg(test)]
@@ -1,4 +1,4 @@
-use crate::error::Result;
+use crate::{error::Result, player::Backend};
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use std::{io, path::PathBuf};
@@ -12,6 +12,14 @@ pub struct Config {
     pub visualizer_gradient: [(u8, u8, u8); 2],
     pub keybindings: Shortcuts,
     pub mpv_ao: Option<String>,
+    pub output_device: Option<String>,
+    pub replaygain: Option<String>,
+    pub player_backend: Option<Backend>,
+    pub restore_session: bool,
+    pub save_session_on_track_change: bool,
+    /// Write the paths of new songs relative to [crate::m3u::relative_paths_base]
+    pub relative_paths: bool,
+    pub music_dir: Option<String>,
 }
 
 static INSTANCE: OnceCell<Config> = OnceCell::new();
@@ -54,6 +62,20 @@ impl Config {
         }
 
         self.mpv_ao = other.mpv_ao;
+        self.output_device = other.output_device;
+        self.replaygain = other.replaygain;
+        self.player_backend = other.player_backend;
+        self.music_dir = other.music_dir;
+
+        if let Some(restore_session) = other.restore_session {
+            self.restore_session = restore_session;
+        }
+        if let Some(save_session_on_track_change) = other.save_session_on_track_change {
+            self.save_session_on_track_change = save_session_on_track_change;
+        }
+        if let Some(relative_paths) = other.relative_paths {
+            self.relative_paths = relative_paths;
+        }
 
         self
     }
@@ -110,6 +132,13 @@ pub struct OptionalConfig {
     pub visualizer_gradient: Option<[Color; 2]>,
     pub keybindings: Option<Shortcuts>,
     pub mpv_ao: Option<String>,
+    pub output_device: Option<String>,
+    pub replaygain: Option<String>,
+    pub player_backend: Option<Backend>,
+    pub restore_session: Option<bool>,
+    pub save_session_on_track_change: Option<bool>,
+    pub relative_paths: Option<bool>,
+    pub music_dir: Option<String>,
 }
 
 impl OptionalConfig {
@@ -11,10 +11,30 @@ use super::command::Command;
 #[derive(Debug, Clone)]
 pub enum Event {
     SecondTick,
-    SongAdded { playlist: String, song: String },
+    SongAdded {
+        playlist: String,
+        song: String,
+    },
+    /// Songs couldn't be added to a playlist, like when it was renamed or deleted meanwhile
+    AddSongFailed(String),
     ChangedPlaylist,
     Command(Command),
     Terminal(CrosstermEvent),
+
+    /// Sent by the [Player](crate::player::Player) when another song started playing, or when the
+    /// title of the current one changed
+    TrackChanged,
+    PauseChanged(bool),
+    /// A seek finished, so the playback position jumped
+    Seeked,
+    /// A song finished playing
+    EndOfFile,
+    /// The volume changed, or the player was muted or unmuted
+    VolumeChanged,
+    /// Songs were added to the queue, or removed or moved
+    QueueChanged,
+    /// Something went wrong in the player, like a song that failed to decode
+    PlayerError(String),
 }
 
 pub struct Channel {
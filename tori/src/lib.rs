@@ -6,6 +6,7 @@ pub mod config;
 pub mod error;
 pub mod m3u;
 pub mod player;
+pub mod session;
 pub mod visualizer;
 
 mod dbglog;
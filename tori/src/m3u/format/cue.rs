@@ -0,0 +1,255 @@
+//! CUE sheets, which split files into tracks, like the image of a whole CD:
+//!
+//! ```text
+//! PERFORMER "Miles Davis"
+//! TITLE "Kind of Blue"
+//! FILE "Kind of Blue.flac" WAVE
+//!   TRACK 01 AUDIO
+//!     TITLE "So What"
+//!     INDEX 01 00:00:00
+//!   TRACK 02 AUDIO
+//!     TITLE "Freddie Freeloader"
+//!     INDEX 01 09:22:32
+//! ```
+//!
+//! Every track becomes a song that plays a section of its file: from its `INDEX 01` to the start
+//! of the next track of the same file.
+
+use std::time::Duration;
+
+use crate::{error::Result, m3u::Song};
+
+/// The last part of the MM:SS:FF timestamps are frames, 75 per second
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+pub fn parse(content: &str) -> Result<Vec<Song>> {
+    let mut songs: Vec<Song> = Vec::new();
+    let mut file: Option<String> = None;
+    // Outside of a track, PERFORMER and TITLE are the ones of the album
+    let mut album_artist = None;
+    let mut album = None;
+    let mut in_track = false;
+
+    for line in content.lines() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+
+        match command.to_uppercase().as_str() {
+            "FILE" => {
+                file = Some(
+                    match args.strip_prefix('"') {
+                        Some(quoted) => quoted.split_once('"').map_or(quoted, |(f, _)| f),
+                        // The file type comes after the name
+                        None => args.rsplit_once(' ').map_or(args, |(f, _)| f),
+                    }
+                    .to_string(),
+                );
+                in_track = false;
+            }
+            "TRACK" => {
+                let path = file
+                    .clone()
+                    .ok_or("The CUE sheet has a TRACK before any FILE")?;
+                let number = args.split_whitespace().next().unwrap_or_default();
+                songs.push(Song {
+                    title: format!("Track {}", number),
+                    path,
+                    artist: album_artist.clone(),
+                    album: album.clone(),
+                    ..Default::default()
+                });
+                in_track = true;
+            }
+            "TITLE" => match songs.last_mut() {
+                Some(song) if in_track => song.title = unquote(args),
+                _ => album = Some(unquote(args)),
+            },
+            "PERFORMER" => match songs.last_mut() {
+                Some(song) if in_track => song.artist = Some(unquote(args)),
+                _ => album_artist = Some(unquote(args)),
+            },
+            "INDEX" => {
+                let Some(song) = songs.last_mut().filter(|_| in_track) else {
+                    continue;
+                };
+                let (number, time) = args
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("Invalid CUE index: {}", line))?;
+                let time = parse_timestamp(time.trim())
+                    .ok_or_else(|| format!("Invalid CUE timestamp: {}", line))?;
+                // INDEX 00 is the pregap, and the track starts at INDEX 01
+                if number == "01" || song.start.is_none() {
+                    song.start = Some(time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // A track ends where the next one of the same file starts, and the last one at the end
+    for i in 1..songs.len() {
+        if songs[i - 1].path == songs[i].path {
+            songs[i - 1].end = songs[i].start;
+        }
+    }
+    for song in &mut songs {
+        if let (Some(start), Some(end)) = (song.start, song.end) {
+            song.duration = Duration::from_secs_f64((end - start).max(0.0));
+        }
+    }
+
+    Ok(songs)
+}
+
+/// Writes every song as a track. Consecutive songs of the same file share its FILE line, so the
+/// tracks of an imported CUE sheet are written back like they were.
+pub fn serialize(songs: &[Song]) -> Result<String> {
+    let mut s = String::new();
+    let mut file = None;
+
+    for (i, song) in songs.iter().enumerate() {
+        if song.is_url() {
+            return Err(format!("CUE sheets can only have local files, not {}", song.path).into());
+        }
+
+        if file != Some(&song.path) {
+            s += &format!("FILE {} {}\n", quote(&song.path), file_type(&song.path));
+            file = Some(&song.path);
+        }
+        s += &format!("  TRACK {:02} AUDIO\n", i + 1);
+        s += &format!("    TITLE {}\n", quote(&song.title));
+        if let Some(artist) = &song.artist {
+            s += &format!("    PERFORMER {}\n", quote(artist));
+        }
+        s += &format!("    INDEX 01 {}\n", timestamp(song.start.unwrap_or(0.0)));
+    }
+
+    Ok(s)
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_string()
+}
+
+/// CUE sheets can't escape quotes, so they're replaced
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "'"))
+}
+
+fn file_type(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+    match extension.as_deref() {
+        Some("mp3") => "MP3",
+        Some("aif") | Some("aiff") => "AIFF",
+        _ => "WAVE",
+    }
+}
+
+/// Parses an MM:SS:FF timestamp into seconds
+fn parse_timestamp(s: &str) -> Option<f64> {
+    let mut parts = s.split(':').map(|p| p.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(minutes as f64 * 60.0 + seconds as f64 + frames as f64 / FRAMES_PER_SECOND)
+}
+
+fn timestamp(seconds: f64) -> String {
+    let frames = (seconds * FRAMES_PER_SECOND).round() as u64;
+    let fps = FRAMES_PER_SECOND as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / fps / 60,
+        frames / fps % 60,
+        frames % fps
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIND_OF_BLUE: &str = r#"REM GENRE Jazz
+PERFORMER "Miles Davis"
+TITLE "Kind of Blue"
+FILE "Kind of Blue.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "So What"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Freddie Freeloader"
+    INDEX 00 09:20:00
+    INDEX 01 09:22:30
+  TRACK 03 AUDIO
+    TITLE "Blue in Green"
+    PERFORMER "Miles Davis & Bill Evans"
+    INDEX 01 19:08:00
+FILE bonus.mp3 MP3
+  TRACK 04 AUDIO
+    INDEX 01 00:00:00
+"#;
+
+    #[test]
+    fn test_parse() {
+        let songs = parse(KIND_OF_BLUE).unwrap();
+        let song = |title: &str, path: &str, artist: &str, start: f64, end: Option<f64>| Song {
+            title: title.into(),
+            duration: end.map_or(Duration::ZERO, |end| Duration::from_secs_f64(end - start)),
+            path: path.into(),
+            artist: Some(artist.into()),
+            album: Some("Kind of Blue".into()),
+            start: Some(start),
+            end,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            songs,
+            [
+                song(
+                    "So What",
+                    "Kind of Blue.flac",
+                    "Miles Davis",
+                    0.0,
+                    Some(562.4)
+                ),
+                song(
+                    "Freddie Freeloader",
+                    "Kind of Blue.flac",
+                    "Miles Davis",
+                    562.4,
+                    Some(1148.0)
+                ),
+                song(
+                    "Blue in Green",
+                    "Kind of Blue.flac",
+                    "Miles Davis & Bill Evans",
+                    1148.0,
+                    None
+                ),
+                song("Track 04", "bonus.mp3", "Miles Davis", 0.0, None),
+            ]
+        );
+
+        assert!(parse("TRACK 01 AUDIO").is_err());
+        assert!(parse("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 1:2").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let songs = parse(KIND_OF_BLUE).unwrap();
+        let serialized = serialize(&songs).unwrap();
+        assert!(serialized.starts_with("FILE \"Kind of Blue.flac\" WAVE\n  TRACK 01 AUDIO\n"));
+        assert!(serialized.contains("FILE \"bonus.mp3\" MP3\n"));
+        assert!(serialized.contains("    INDEX 01 09:22:30\n"));
+
+        // The album isn't written back
+        let mut parsed = parse(&serialized).unwrap();
+        for song in &mut parsed {
+            song.album = Some("Kind of Blue".into());
+        }
+        assert_eq!(parsed, songs);
+    }
+}
@@ -0,0 +1,121 @@
+//! The playlist formats tori can import and export. tori's own playlists are always M3U, and the
+//! other formats are converted from and to it.
+
+use std::path::Path;
+
+use super::{Parser, Song};
+use crate::error::Result;
+
+mod cue;
+mod pls;
+mod xspf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    M3u,
+    Pls,
+    Xspf,
+    /// CUE sheets, where every track is a section of a file
+    Cue,
+}
+
+impl Format {
+    /// The format of the playlist at `path`, according to its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            "cue" => Some(Self::Cue),
+            _ => None,
+        }
+    }
+
+    /// Parses the songs of a playlist. Relative paths are relative to `dir`, the directory of the
+    /// playlist, and are joined to it.
+    pub fn parse(self, content: &str, dir: &Path) -> Result<Vec<Song>> {
+        let mut songs = match self {
+            Self::M3u => Parser::from_string(content).all_songs()?,
+            Self::Pls => pls::parse(content)?,
+            Self::Xspf => xspf::parse(content, dir)?,
+            Self::Cue => cue::parse(content)?,
+        };
+        for song in &mut songs {
+            song.resolve_path(dir);
+        }
+        Ok(songs)
+    }
+
+    /// Only M3U and CUE sheets keep where the songs start and end in their files
+    pub fn serialize(self, songs: &[Song]) -> Result<String> {
+        match self {
+            Self::M3u => {
+                let mut s = String::from("#EXTM3U\n");
+                s.extend(songs.iter().map(Song::serialize));
+                Ok(s)
+            }
+            Self::Pls => Ok(pls::serialize(songs)),
+            Self::Xspf => Ok(xspf::serialize(songs)),
+            Self::Cue => cue::serialize(songs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        for (path, format) in [
+            ("rock.m3u8", Some(Format::M3u)),
+            ("/home/alice/rock.M3U", Some(Format::M3u)),
+            ("rock.pls", Some(Format::Pls)),
+            ("rock.xspf", Some(Format::Xspf)),
+            ("album.cue", Some(Format::Cue)),
+            ("album.flac", None),
+            ("rock", None),
+        ] {
+            assert_eq!(Format::from_path(Path::new(path)), format);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let songs = vec![
+            Song {
+                title: "Paranoid".into(),
+                duration: std::time::Duration::from_secs(168),
+                path: "/music/paranoid.mp3".into(),
+                artist: Some("Black Sabbath".into()),
+                album: Some("Paranoid".into()),
+                ..Default::default()
+            },
+            Song {
+                title: "So What & Blue in Green".into(),
+                duration: std::time::Duration::from_secs(565),
+                path: "/music/kind of blue.flac".into(),
+                artist: Some("Miles Davis".into()),
+                ..Default::default()
+            },
+        ];
+
+        for format in [Format::M3u, Format::Pls, Format::Xspf] {
+            let serialized = format.serialize(&songs).unwrap();
+            let mut parsed = format.parse(&serialized, Path::new("/")).unwrap();
+            // M3U songs remember the lines they were read from
+            for song in &mut parsed {
+                song.lines.clear();
+            }
+            if format == Format::Pls {
+                // PLS has no artist or album
+                for (parsed, song) in parsed.iter_mut().zip(&songs) {
+                    parsed.artist = song.artist.clone();
+                    parsed.album = song.album.clone();
+                }
+            }
+            assert_eq!(parsed, songs, "{:?}", format);
+        }
+    }
+}
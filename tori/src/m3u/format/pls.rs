@@ -0,0 +1,129 @@
+//! PLS playlists, an INI file with a `FileN`, `TitleN` and `LengthN` key for every song:
+//!
+//! ```ini
+//! [playlist]
+//! File1=/music/paranoid.mp3
+//! Title1=Paranoid
+//! Length1=168
+//! NumberOfEntries=1
+//! Version=2
+//! ```
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{error::Result, m3u::Song};
+
+pub fn parse(content: &str) -> Result<Vec<Song>> {
+    let mut lines = content
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty());
+
+    if !lines
+        .next()
+        .is_some_and(|line| line.eq_ignore_ascii_case("[playlist]"))
+    {
+        return Err("PLS playlists should start with [playlist]".into());
+    }
+
+    // The entries may come in any order, and some numbers may be missing
+    let mut songs: BTreeMap<usize, Song> = BTreeMap::new();
+    for line in lines {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let Some((name, index)) = key
+            .find(|c: char| c.is_ascii_digit())
+            .map(|i| key.split_at(i))
+        else {
+            // NumberOfEntries and Version
+            continue;
+        };
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+
+        let song = songs.entry(index).or_default();
+        let value = value.trim();
+        match name {
+            "file" => song.path = value.into(),
+            "title" => song.title = value.into(),
+            // Streams have a length of -1
+            "length" => song.duration = Duration::from_secs(value.parse().unwrap_or(0)),
+            _ => {}
+        }
+    }
+
+    Ok(songs
+        .into_values()
+        .filter(|song| !song.path.is_empty())
+        .map(|mut song| {
+            if song.title.is_empty() {
+                song.title = song.path.clone();
+            }
+            song
+        })
+        .collect())
+}
+
+pub fn serialize(songs: &[Song]) -> String {
+    let mut s = String::from("[playlist]\n");
+    for (i, song) in songs.iter().enumerate() {
+        let length = match song.duration.as_secs() {
+            0 if song.is_url() => -1,
+            secs => secs as i64,
+        };
+        s += &format!("File{}={}\n", i + 1, song.path);
+        s += &format!("Title{}={}\n", i + 1, song.title);
+        s += &format!("Length{}={}\n", i + 1, length);
+    }
+    s += &format!("NumberOfEntries={}\nVersion=2\n", songs.len());
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let songs = parse(
+            "[Playlist]
+            NumberOfEntries=3
+            File2=/music/paranoid.mp3
+            Title2=Paranoid
+            Length2=168
+            File1=http://example.com/radio
+            Title1=Radio
+            Length1=-1
+            File3=/music/smoke.mp3
+            Version=2",
+        )
+        .unwrap();
+
+        assert_eq!(
+            songs,
+            [
+                Song {
+                    title: "Radio".into(),
+                    path: "http://example.com/radio".into(),
+                    ..Default::default()
+                },
+                Song {
+                    title: "Paranoid".into(),
+                    duration: Duration::from_secs(168),
+                    path: "/music/paranoid.mp3".into(),
+                    ..Default::default()
+                },
+                Song {
+                    title: "/music/smoke.mp3".into(),
+                    path: "/music/smoke.mp3".into(),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        assert!(parse("#EXTM3U\n/music/paranoid.mp3").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! XSPF playlists, the XML format of VLC and others. Songs are `<track>`s with a `<location>`
+//! URI, and tori reads and writes their title, creator, album, image and duration.
+
+use std::{path::Path, time::Duration};
+
+use url::Url;
+
+use crate::{error::Result, m3u::Song};
+
+pub fn parse(content: &str, dir: &Path) -> Result<Vec<Song>> {
+    let doc = roxmltree::Document::parse(content)
+        .map_err(|e| format!("Couldn't parse the XSPF playlist: {}", e))?;
+
+    // Relative locations are relative to the playlist
+    let base = Url::from_directory_path(dir).ok();
+
+    let songs = doc
+        .descendants()
+        .filter(|node| node.tag_name().name() == "track")
+        .filter_map(|track| {
+            let field = |name: &str| {
+                track
+                    .children()
+                    .find(|node| node.tag_name().name() == name)
+                    .and_then(|node| node.text())
+                    .map(|text| text.trim().to_string())
+            };
+
+            let path = location_to_path(&field("location")?, base.as_ref());
+            Some(Song {
+                title: field("title").unwrap_or_else(|| path.clone()),
+                duration: Duration::from_millis(
+                    field("duration")
+                        .and_then(|ms| ms.parse().ok())
+                        .unwrap_or(0),
+                ),
+                artist: field("creator"),
+                album: field("album"),
+                image: field("image"),
+                path,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    Ok(songs)
+}
+
+/// Local files are `file://` URIs, and everything else is left as it is
+fn location_to_path(location: &str, base: Option<&Url>) -> String {
+    let url = match base {
+        Some(base) => base.join(location),
+        None => Url::parse(location),
+    };
+    match url {
+        Ok(url) if url.scheme() == "file" => url
+            .to_file_path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| location.to_string()),
+        _ => location.to_string(),
+    }
+}
+
+pub fn serialize(songs: &[Song]) -> String {
+    let mut s = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  \
+           <trackList>\n",
+    );
+
+    for song in songs {
+        let location = match Url::from_file_path(&song.path) {
+            Ok(url) if !song.is_url() => url.to_string(),
+            _ => song.path.clone(),
+        };
+
+        s += "    <track>\n";
+        s += &element("location", &location);
+        s += &element("title", &song.title);
+        for (name, value) in [
+            ("creator", &song.artist),
+            ("album", &song.album),
+            ("image", &song.image),
+        ] {
+            if let Some(value) = value {
+                s += &element(name, value);
+            }
+        }
+        if !song.duration.is_zero() {
+            s += &element("duration", &song.duration.as_millis().to_string());
+        }
+        s += "    </track>\n";
+    }
+
+    s += "  </trackList>\n</playlist>\n";
+    s
+}
+
+/// An element of a `<track>`, with its text escaped
+fn element(name: &str, text: &str) -> String {
+    let text = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!("      <{0}>{1}</{0}>\n", name, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let songs = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/" xmlns:vlc="http://www.videolan.org/vlc/playlist/ns/0/">
+              <title>Rock</title>
+              <trackList>
+                <track>
+                  <location>file:///music/Black%20Sabbath/paranoid.mp3</location>
+                  <title>Paranoid</title>
+                  <creator>Black Sabbath</creator>
+                  <duration>168000</duration>
+                  <extension application="http://www.videolan.org/vlc/playlist/0">
+                    <vlc:id>0</vlc:id>
+                  </extension>
+                </track>
+                <track>
+                  <location>smoke%20on%20the%20water.mp3</location>
+                </track>
+                <track>
+                  <location>https://example.com/radio?a=1&amp;b=2</location>
+                  <title>Radio</title>
+                </track>
+              </trackList>
+            </playlist>"#,
+            Path::new("/home/alice/Music"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            songs,
+            [
+                Song {
+                    title: "Paranoid".into(),
+                    duration: Duration::from_secs(168),
+                    path: "/music/Black Sabbath/paranoid.mp3".into(),
+                    artist: Some("Black Sabbath".into()),
+                    ..Default::default()
+                },
+                Song {
+                    title: "/home/alice/Music/smoke on the water.mp3".into(),
+                    path: "/home/alice/Music/smoke on the water.mp3".into(),
+                    ..Default::default()
+                },
+                Song {
+                    title: "Radio".into(),
+                    path: "https://example.com/radio?a=1&b=2".into(),
+                    ..Default::default()
+                },
+            ]
+        );
+
+        assert!(parse("[playlist]", Path::new("/")).is_err());
+    }
+}
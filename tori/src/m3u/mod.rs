@@ -1,5 +1,6 @@
-use std::io::{self, ErrorKind, Read, Seek, Write};
-
+use std::collections::HashMap;
+use std::mem::discriminant;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use crate::{config::Config, error::Result};
@@ -8,8 +9,15 @@ pub mod stringreader;
 pub use stringreader::StringReader;
 
 pub mod parser;
+use parser::Ext;
 pub use parser::Parser;
 
+pub mod format;
+pub use format::Format;
+
+pub mod playlist;
+pub use playlist::Playlist;
+
 pub mod playlist_management;
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -17,6 +25,25 @@ pub struct Song {
     pub title: String,
     pub duration: Duration,
     pub path: String,
+    /// The `key="value"` attributes of the `#EXTINF` line, in the order they were written, like
+    /// `tvg-logo` or `group-title` in IPTV playlists
+    pub attributes: Vec<(String, String)>,
+    /// From `#EXTART`
+    pub artist: Option<String>,
+    /// From `#EXTALB`
+    pub album: Option<String>,
+    /// From `#EXTGRP`
+    pub group: Option<String>,
+    /// Cover art, from `#EXTIMG`
+    pub image: Option<String>,
+    /// Where the song starts and ends in its file, in seconds, for songs that share a file like
+    /// the tracks of a CUE sheet. From VLC's `#EXTVLCOPT:start-time` and `#EXTVLCOPT:stop-time`.
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+    /// The lines that came before the path, as they were read: the directives above, and lines
+    /// tori doesn't understand, like comments or the directives of other players. Only the
+    /// directives whose value changed are written differently.
+    pub lines: Vec<String>,
 }
 
 impl Song {
@@ -55,10 +82,15 @@ impl Song {
         let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
         let title = metadata["title"].as_str().unwrap_or("?").into();
         let duration = Duration::from_secs_f64(metadata["duration"].as_f64().unwrap_or(0.0));
+        let field = |key: &str| metadata[key].as_str().map(String::from);
         Ok(Song {
             title,
             duration,
             path: url.into(),
+            artist: field("artist"),
+            album: field("album"),
+            image: field("thumbnail"),
+            ..Default::default()
         })
     }
 
@@ -89,15 +121,11 @@ impl Song {
             .as_ref()
             .and_then(|t| t.primary_tag().or(t.first_tag()));
 
-        let title = match (
-            tag.and_then(Accessor::artist),
-            tag.and_then(Accessor::title),
-        ) {
-            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
-            (Some(artist), None) => format!("{} - ?", artist),
-            (None, Some(title)) => title.to_string(),
-            (None, None) => default_title(),
-        };
+        let title = tag
+            .and_then(Accessor::title)
+            .map_or_else(default_title, |title| title.to_string());
+        let artist = tag.and_then(Accessor::artist).map(|a| a.to_string());
+        let album = tag.and_then(Accessor::album).map(|a| a.to_string());
 
         let duration = tagged_file
             .map(|t| t.properties().duration())
@@ -107,61 +135,172 @@ impl Song {
             title,
             duration,
             path: path.into(),
+            artist,
+            album,
+            ..Default::default()
         })
     }
 
-    pub fn serialize(&self) -> String {
-        let duration = self.duration.as_secs();
-        format!("#EXTINF:{},{}\n{}\n", duration, self.title, self.path)
+    /// Where the song starts and ends in its file, if it's only a section of it
+    pub fn section(&self) -> Option<(f64, Option<f64>)> {
+        match (self.start, self.end) {
+            (None, None) => None,
+            (start, end) => Some((start.unwrap_or(0.0), end)),
+        }
     }
 
-    pub fn add_to_playlist(&self, playlist_name: &str) -> Result<()> {
-        let path = Config::playlist_path(playlist_name);
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .read(true)
-            .append(true)
-            .open(path)?;
-
-        // Write a #EXTM3U header if it doesn't exist
-        file.rewind()?;
-        let mut buf = vec![0u8; "#EXTM3U".len()];
-        let has_extm3u = match file.read_exact(&mut buf) {
-            Ok(()) if buf == b"#EXTM3U" => true,
-            Ok(()) => false,
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
-            Err(e) => {
-                return Err(Box::new(e));
-            }
-        };
+    /// Whether the song is a URL instead of a local file
+    pub fn is_url(&self) -> bool {
+        is_url(&self.path)
+    }
 
-        if !has_extm3u {
-            let mut buf = Vec::new();
-            file.rewind()?;
-            file.read_to_end(&mut buf)?;
-            file.set_len(0)?;
-            file.write_all(b"#EXTM3U\n")?;
-            file.write_all(&buf)?;
+    /// Joins a relative path to `base`, so the player finds the song no matter where tori was
+    /// started. URLs and absolute paths are left as they are.
+    pub fn resolve_path(&mut self, base: &Path) {
+        if !self.is_url() && Path::new(&self.path).is_relative() {
+            self.path = base.join(&self.path).to_string_lossy().into_owned();
         }
+    }
 
-        // Check if the file ends in a newline
-        file.seek(io::SeekFrom::End(-1))?;
-        buf = vec![0u8];
-        let ends_with_newline = match file.read_exact(&mut buf) {
-            Ok(()) if buf == b"\n" => true,
-            Ok(()) => false,
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
-            Err(e) => {
-                return Err(Box::new(e));
-            }
+    /// Makes the path relative to `base` if the song is inside it. The components are joined with
+    /// '/', which every platform understands, so the playlist can be shared between them.
+    pub fn relativize_path(&mut self, base: &Path) {
+        if self.is_url() {
+            return;
+        }
+        if let Ok(relative) = Path::new(&self.path).strip_prefix(base) {
+            self.path = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+        }
+    }
+
+    /// The directives of the song, in the order they're written when it has no lines yet
+    fn directives(&self) -> Vec<Ext> {
+        let extinf = Ext::Extinf(self.duration, self.attributes.clone(), self.title.clone());
+        [
+            Some(extinf),
+            self.album.clone().map(Ext::Extalb),
+            self.artist.clone().map(Ext::Extart),
+            self.group.clone().map(Ext::Extgrp),
+            self.image.clone().map(Ext::Extimg),
+            self.start.map(Ext::StartTime),
+            self.end.map(Ext::StopTime),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// The lines of the song. The lines it was read from are written back as they were, unless
+    /// the value of their directive changed. Those are written again in the same place, or left
+    /// out if the value was removed, and the new directives come after the other lines.
+    pub fn serialize(&self) -> String {
+        // When a directive appears more than once, the parser keeps the last one, so the lines
+        // before it are written back as they were even if the value changed
+        let parsed: Vec<_> = self
+            .lines
+            .iter()
+            .map(|l| parser::parse_extline(l.trim()))
+            .collect();
+        let last: HashMap<_, _> = parsed
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ext)| Some((discriminant(ext.as_ref()?), i)))
+            .collect();
+        let directives = self.directives();
+        let current = |ext: &Ext| {
+            directives
+                .iter()
+                .find(|d| discriminant(*d) == discriminant(ext))
         };
 
-        if !ends_with_newline {
-            file.write_all(b"\n")?;
+        let mut s = String::new();
+        for (i, (line, ext)) in self.lines.iter().zip(&parsed).enumerate() {
+            let line = match ext {
+                Some(ext) if last[&discriminant(ext)] == i => match current(ext) {
+                    Some(value) if value == ext => line.clone(),
+                    Some(value) => value.to_line(Some(line.trim())),
+                    None => continue,
+                },
+                _ => line.clone(),
+            };
+            s += &line;
+            s.push('\n');
         }
+        for directive in &directives {
+            if !last.contains_key(&discriminant(directive)) {
+                s += &directive.to_line(None);
+                s.push('\n');
+            }
+        }
+        s += &self.path;
+        s.push('\n');
+        s
+    }
+}
+
+/// Whether `path` is a URL, like `https://...` or `ytdl://...`, instead of a local file
+pub fn is_url(path: &str) -> bool {
+    path.contains("://")
+}
+
+/// The directory the relative paths of the playlist at `playlist_path` are relative to: the
+/// `music_dir` option if it's set, or else the directory of the playlist.
+pub fn relative_paths_base(playlist_path: &Path) -> PathBuf {
+    match &Config::global().music_dir {
+        Some(music_dir) => PathBuf::from(music_dir),
+        None => playlist_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Write the serialized song
-        file.write_all(self.serialize().as_bytes())?;
-        Ok(())
+    fn song(path: &str) -> Song {
+        Song {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let base = Path::new("/home/alice/Music");
+        for (path, resolved) in [
+            ("metal/paranoid.mp3", "/home/alice/Music/metal/paranoid.mp3"),
+            ("../paranoid.mp3", "/home/alice/Music/../paranoid.mp3"),
+            ("/music/paranoid.mp3", "/music/paranoid.mp3"),
+            ("https://example.com/a.mp3", "https://example.com/a.mp3"),
+            ("ytdl://ytsearch:paranoid", "ytdl://ytsearch:paranoid"),
+        ] {
+            let mut song = song(path);
+            song.resolve_path(base);
+            assert_eq!(song.path, resolved);
+        }
+    }
+
+    #[test]
+    fn test_relativize_path() {
+        let base = Path::new("/home/alice/Music");
+        for (path, relative) in [
+            ("/home/alice/Music/metal/paranoid.mp3", "metal/paranoid.mp3"),
+            ("/home/alice/Musicals/a.mp3", "/home/alice/Musicals/a.mp3"),
+            ("/music/paranoid.mp3", "/music/paranoid.mp3"),
+            ("https://example.com/a.mp3", "https://example.com/a.mp3"),
+        ] {
+            let mut song = song(path);
+            song.relativize_path(base);
+            assert_eq!(song.path, relative);
+
+            song.resolve_path(base);
+            assert_eq!(song.path, path);
+        }
     }
 }
@@ -14,7 +14,6 @@ use super::StringReader;
 #[derive(Debug)]
 pub enum ParserError {
     Io(io::Error),
-    UnknownExtline(String),
 }
 
 impl Error for ParserError {}
@@ -29,7 +28,6 @@ impl std::fmt::Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ParserError::Io(e) => write!(f, "IO error: {}", e),
-            ParserError::UnknownExtline(s) => write!(f, "Unknown EXT directive: {}", s),
         }
     }
 }
@@ -39,10 +37,63 @@ pub type Result<T> = std::result::Result<T, ParserError>;
 //////////////////////////////////
 //        Ext Directives        //
 //////////////////////////////////
-#[derive(Debug, PartialEq)]
-enum Ext {
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Ext {
     Extm3u,
-    Extinf(Duration, String),
+    /// The duration, the `key="value"` attributes and the title
+    Extinf(Duration, Vec<(String, String)>, String),
+    Extalb(String),
+    Extart(String),
+    Extgrp(String),
+    Extimg(String),
+    /// `#EXTVLCOPT:start-time=...`
+    StartTime(f64),
+    /// `#EXTVLCOPT:stop-time=...`
+    StopTime(f64),
+}
+
+impl Ext {
+    /// The line of the directive. `original` is the line it replaces, if any: the parts of it
+    /// that didn't change are kept as they were, like the `-1` duration of a stream whose title
+    /// was changed.
+    pub(super) fn to_line(&self, original: Option<&str>) -> String {
+        use Ext::*;
+        match self {
+            Extm3u => "#EXTM3U".into(),
+            Extinf(duration, attributes, title) => {
+                let original_head = original
+                    .and_then(|line| line.strip_prefix("#EXTINF:"))
+                    .map(|line| split_extinf(line).0)
+                    .filter(|head| parse_extinf_head(head) == (*duration, attributes.clone()));
+                let head = match original_head {
+                    Some(head) => head.to_string(),
+                    None => {
+                        let mut head = format_duration(*duration);
+                        for (key, value) in attributes {
+                            head += &format!(" {}=\"{}\"", key, value);
+                        }
+                        head
+                    }
+                };
+                format!("#EXTINF:{},{}", head, title)
+            }
+            Extalb(album) => format!("#EXTALB:{}", album),
+            Extart(artist) => format!("#EXTART:{}", artist),
+            Extgrp(group) => format!("#EXTGRP:{}", group),
+            Extimg(image) => format!("#EXTIMG:{}", image),
+            StartTime(time) => format!("#EXTVLCOPT:start-time={}", time),
+            StopTime(time) => format!("#EXTVLCOPT:stop-time={}", time),
+        }
+    }
+}
+
+/// Whole seconds are written without a fraction, like most players do
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        duration.as_secs().to_string()
+    } else {
+        duration.as_secs_f64().to_string()
+    }
 }
 
 //////////////////////////////
@@ -66,7 +117,9 @@ impl<R: Read> LineReader for BufReader<R> {
 pub struct Parser<L: LineReader> {
     reader: L,
     line_buf: Option<String>,
+    /// Bytes read so far, not counting the line in `line_buf`
     cursor: usize,
+    line_buf_bytes: usize,
 }
 
 impl Parser<BufReader<fs::File>> {
@@ -75,6 +128,7 @@ impl Parser<BufReader<fs::File>> {
             reader: BufReader::new(reader),
             line_buf: None,
             cursor: 0,
+            line_buf_bytes: 0,
         }
     }
 
@@ -90,6 +144,7 @@ impl<'s> Parser<StringReader<'s>> {
             reader: StringReader::new(s),
             line_buf: None,
             cursor: 0,
+            line_buf_bytes: 0,
         }
     }
 }
@@ -100,6 +155,7 @@ impl<R: Read> Parser<BufReader<R>> {
             reader: BufReader::new(reader),
             line_buf: None,
             cursor: 0,
+            line_buf_bytes: 0,
         }
     }
 }
@@ -116,7 +172,7 @@ impl<L: LineReader> Parser<L> {
                 return Ok(None);
             }
 
-            self.cursor += bytes;
+            self.line_buf_bytes = bytes;
 
             let is_nl = |c| c == Some(b'\n') || c == Some(b'\r');
             while is_nl(line.as_bytes().last().copied()) {
@@ -130,33 +186,50 @@ impl<L: LineReader> Parser<L> {
 
     fn consume_line(&mut self) -> Result<Option<String>> {
         self.peek_line()?;
+        self.cursor += std::mem::take(&mut self.line_buf_bytes);
         Ok(self.line_buf.take())
     }
 
+    /// Parses the `#EXTM3U` header, along with the `#PLAYLIST` directives right after it, which
+    /// are about the whole playlist instead of the first song
     pub fn next_header(&mut self) -> Result<bool> {
         match self.peek_line()? {
             Some(line) if line.starts_with("#EXTM3U") => {
                 self.consume_line()?;
+                while matches!(self.peek_line()?, Some(line) if line.starts_with("#PLAYLIST:")) {
+                    self.consume_line()?;
+                }
                 Ok(true)
             }
             _otherwise => Ok(false),
         }
     }
 
+    /// Parses the next song. Its lines are kept as they were read, along with comments and
+    /// directives tori doesn't know, so it can be written back without changing them.
     pub fn next_song(&mut self) -> Result<Option<Song>> {
         let mut song = Song::default();
-        while let Some(line) = self.consume_line()? {
-            let line = line.trim();
+        while let Some(raw) = self.consume_line()? {
+            let line = raw.trim();
             if line.is_empty() {
-            } else if line.starts_with("#EXT") {
+            } else if line.starts_with('#') {
                 use Ext::*;
-                match parse_extline(line)? {
-                    Extm3u => {}
-                    Extinf(d, t) => {
+                match parse_extline(line) {
+                    Some(Extm3u) => continue,
+                    Some(Extinf(d, a, t)) => {
                         song.duration = d;
+                        song.attributes = a;
                         song.title = t;
                     }
+                    Some(Extalb(album)) => song.album = Some(album),
+                    Some(Extart(artist)) => song.artist = Some(artist),
+                    Some(Extgrp(group)) => song.group = Some(group),
+                    Some(Extimg(image)) => song.image = Some(image),
+                    Some(StartTime(start)) => song.start = Some(start),
+                    Some(StopTime(end)) => song.end = Some(end),
+                    None => {}
                 }
+                song.lines.push(raw);
             } else {
                 song.path = line.into();
                 if song.title.is_empty() {
@@ -177,25 +250,83 @@ impl<L: LineReader> Parser<L> {
     }
 }
 
-fn parse_extline(line: &str) -> Result<Ext> {
+/// Parses the directives tori understands, or returns None for any other line starting with '#'
+pub(super) fn parse_extline(line: &str) -> Option<Ext> {
     use Ext::*;
     if line.starts_with("#EXTM3U") {
-        return Ok(Extm3u);
+        return Some(Extm3u);
     }
 
     if let Some(line) = line.strip_prefix("#EXTINF:") {
-        let mut parts = line.splitn(2, ',');
-        let duration = Duration::from_secs(
-            parts
-                .next()
-                .and_then(|p| p.parse::<f64>().ok())
-                .unwrap_or_default() as u64,
-        );
-        let title = parts.next().unwrap_or_default().to_string();
-        return Ok(Extinf(duration, title));
+        return Some(parse_extinf(line));
     }
 
-    Err(ParserError::UnknownExtline(line.to_string()))
+    let (directive, value) = line.split_once(':')?;
+    let value = value.trim().to_string();
+    match directive {
+        "#EXTALB" => Some(Extalb(value)),
+        "#EXTART" => Some(Extart(value)),
+        "#EXTGRP" => Some(Extgrp(value)),
+        "#EXTIMG" => Some(Extimg(value)),
+        "#EXTVLCOPT" => match value.split_once('=')? {
+            ("start-time", time) => time.parse().ok().map(StartTime),
+            ("stop-time", time) => time.parse().ok().map(StopTime),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses what comes after `#EXTINF:`, like `-1 tvg-logo="logo.png" group-title="Rock",Title`
+fn parse_extinf(line: &str) -> Ext {
+    let (head, title) = split_extinf(line);
+    let (duration, attributes) = parse_extinf_head(head);
+    Ext::Extinf(duration, attributes, title.to_string())
+}
+
+/// Splits what comes after `#EXTINF:` into the duration with the attributes, and the title. The
+/// title starts after the first comma that isn't inside quotes, since attributes may have commas
+/// too.
+fn split_extinf(line: &str) -> (&str, &str) {
+    let mut in_quotes = false;
+    let comma = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        c == ',' && !in_quotes
+    });
+    match comma {
+        Some((i, _)) => (&line[..i], &line[i + 1..]),
+        None => (line, ""),
+    }
+}
+
+/// Parses the duration and the attributes of an `#EXTINF` line. Durations that aren't positive,
+/// like the -1 of streams, become zero.
+fn parse_extinf_head(head: &str) -> (Duration, Vec<(String, String)>) {
+    let head = head.trim_start();
+    let duration_end = head.find(char::is_whitespace).unwrap_or(head.len());
+    let seconds = head[..duration_end].parse::<f64>().unwrap_or_default();
+    let duration = Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or_default();
+    (duration, parse_attributes(&head[duration_end..]))
+}
+
+/// Parses `key="value"` pairs separated by spaces. The quotes are optional when the value has no
+/// spaces.
+fn parse_attributes(mut s: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        s = s.trim_start();
+        let Some((key, rest)) = s.split_once('=') else {
+            return attributes;
+        };
+        let (value, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        attributes.push((key.trim().to_string(), value.to_string()));
+        s = rest;
+    }
 }
 
 #[cfg(test)]
@@ -204,18 +335,40 @@ mod tests {
 
     #[test]
     fn test_extline_parsing() {
-        assert_eq!(parse_extline("#EXTM3U").ok(), Some(Ext::Extm3u));
+        assert_eq!(parse_extline("#EXTM3U"), Some(Ext::Extm3u));
         assert_eq!(
-            parse_extline("#EXTINF:10,Artist - Title").ok(),
+            parse_extline("#EXTINF:10,Artist - Title"),
             Some(Ext::Extinf(
                 Duration::from_secs_f64(10.),
+                vec![],
                 "Artist - Title".into()
             ))
         );
         assert_eq!(
-            parse_extline("#EXTINF:").ok(),
-            Some(Ext::Extinf(Duration::default(), String::default()))
+            parse_extline("#EXTINF:"),
+            Some(Ext::Extinf(Duration::default(), vec![], String::default()))
+        );
+        assert_eq!(
+            parse_extline(r#"#EXTINF:-1 tvg-logo="a, b.png" group-title="Rock" x=1,Title, Live"#),
+            Some(Ext::Extinf(
+                Duration::default(),
+                vec![
+                    ("tvg-logo".into(), "a, b.png".into()),
+                    ("group-title".into(), "Rock".into()),
+                    ("x".into(), "1".into())
+                ],
+                "Title, Live".into()
+            ))
+        );
+        assert_eq!(
+            parse_extline("#EXTALB: Paranoid"),
+            Some(Ext::Extalb("Paranoid".into()))
+        );
+        assert_eq!(
+            parse_extline("#EXTVLCOPT:start-time=10.5"),
+            Some(Ext::StartTime(10.5))
         );
+        assert_eq!(parse_extline("#EXTVLCOPT:network-caching=1000"), None);
     }
 
     #[test]
@@ -231,6 +384,8 @@ mod tests {
             "#,
         );
 
+        // The lines are kept with their indentation, to write them back as they were
+        let indented = |line: &str| format!("{:12}{}", "", line);
         use super::Song;
         assert_eq!(
             parser.all_songs().ok(),
@@ -238,12 +393,16 @@ mod tests {
                 Song {
                     title: "Artist - Title".into(),
                     duration: Duration::from_secs_f64(10.),
-                    path: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into()
+                    path: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into(),
+                    lines: vec![indented("#EXTINF:10,Artist - Title")],
+                    ..Default::default()
                 },
                 Song {
                     title: "Yup".into(),
                     duration: Duration::from_secs_f64(0.),
-                    path: "/path/to/local/song".into()
+                    path: "/path/to/local/song".into(),
+                    lines: vec![indented("#EXTINF:0,Yup")],
+                    ..Default::default()
                 }
             ]),
         );
@@ -258,37 +417,142 @@ mod tests {
 
         assert_eq!(
             parser.all_songs().ok(),
-            Some(vec![
-                Song {
-                    title: "#DOESNOTBEGINWITHEXT".into(),
-                    duration: Duration::default(),
-                    path: "#DOESNOTBEGINWITHEXT".into(),
-                },
-                Song {
-                    title: "something.mp3".into(),
-                    duration: Duration::default(),
-                    path: "something.mp3".into()
-                },
-            ]),
+            Some(vec![Song {
+                title: "something.mp3".into(),
+                duration: Duration::default(),
+                path: "something.mp3".into(),
+                lines: vec![indented("#DOESNOTBEGINWITHEXT")],
+                ..Default::default()
+            }]),
         );
     }
 
     #[test]
-    fn test_extline_errors() {
-        let mut parser = Parser::from_string(
-            r#"
-            #EXTM3U
+    fn test_unknown_lines_round_trip() {
+        let songs = "\
+# Exported by VLC
+#EXTINF:10,Artist - Title
+#EXTVLCOPT:network-caching=1000
+#EXTBYT:1234
+https://www.youtube.com/watch?v=dQw4w9WgXcQ
+#EXTNOTSUPPORTED
+/path/to/local/song
+";
+        let mut parser = Parser::from_string(songs);
+        let parsed = parser.all_songs().unwrap();
+
+        assert_eq!(parsed[0].title, "Artist - Title");
+        assert_eq!(
+            parsed[0].lines,
+            [
+                "# Exported by VLC",
+                "#EXTINF:10,Artist - Title",
+                "#EXTVLCOPT:network-caching=1000",
+                "#EXTBYT:1234"
+            ]
+        );
+        assert_eq!(parsed[1].lines, ["#EXTNOTSUPPORTED"]);
 
-            #EXTINF:10,Artist - Title
-            https://www.youtube.com/watch?v=dQw4w9WgXcQ
-            #EXTNOTSUPPORTED
-            /path/to/local/song
-            "#,
+        // Songs without #EXTINF get one
+        let serialized: String = parsed.iter().map(Song::serialize).collect();
+        assert_eq!(
+            serialized,
+            songs.replace(
+                "/path/to/local/song",
+                "#EXTINF:0,/path/to/local/song\n/path/to/local/song"
+            )
+        );
+    }
+
+    #[test]
+    fn test_extended_attributes_round_trip() {
+        let songs = r#"#EXTINF:-1 tvg-id="radio.1" tvg-logo="https://example.com/logo.png" group-title="News, Talk",Radio 1
+http://example.com/radio1
+#EXTINF:312,Paranoid
+#EXTALB:Paranoid
+#EXTART:Black Sabbath
+#EXTGRP:Rock
+#EXTIMG:cover.jpg
+#EXTVLCOPT:start-time=10
+/music/paranoid.mp3
+"#;
+        let mut parser = Parser::from_string(songs);
+        let parsed = parser.all_songs().unwrap();
+
+        assert_eq!(parsed[0].title, "Radio 1");
+        assert_eq!(parsed[0].duration, Duration::default());
+        assert_eq!(
+            parsed[0].attributes,
+            [
+                ("tvg-id".into(), "radio.1".into()),
+                ("tvg-logo".into(), "https://example.com/logo.png".into()),
+                ("group-title".into(), "News, Talk".into())
+            ]
+        );
+        assert_eq!(
+            parsed[1],
+            Song {
+                title: "Paranoid".into(),
+                duration: Duration::from_secs(312),
+                path: "/music/paranoid.mp3".into(),
+                artist: Some("Black Sabbath".into()),
+                album: Some("Paranoid".into()),
+                group: Some("Rock".into()),
+                image: Some("cover.jpg".into()),
+                start: Some(10.),
+                lines: songs.lines().skip(2).take(6).map(String::from).collect(),
+                ..Default::default()
+            }
+        );
+
+        let serialized: String = parsed.iter().map(Song::serialize).collect();
+        assert_eq!(serialized, songs);
+    }
+
+    #[test]
+    fn test_directives_are_only_rewritten_when_they_change() {
+        let songs = "\
+#EXTINF:-1 tvg-logo=\"logo.png\",Radio 1
+#EXTALB: Live
+http://example.com/radio1
+#EXTINF:312.5,Paranoid
+#EXTART:Black Sabbath
+# a comment
+#EXTALB:  Paranoid
+#EXTIMG:cover.jpg
+/music/paranoid.mp3
+";
+        let mut parsed = Parser::from_string(songs).all_songs().unwrap();
+        assert_eq!(parsed[1].duration, Duration::from_secs_f64(312.5));
+        let serialized: String = parsed.iter().map(Song::serialize).collect();
+        assert_eq!(serialized, songs);
+
+        parsed[0].title = "Radio One".into();
+        parsed[1].album = Some("War Pigs".into());
+        parsed[1].image = None;
+        parsed[1].group = Some("Metal".into());
+        let serialized: String = parsed.iter().map(Song::serialize).collect();
+        assert_eq!(
+            serialized,
+            "\
+#EXTINF:-1 tvg-logo=\"logo.png\",Radio One
+#EXTALB: Live
+http://example.com/radio1
+#EXTINF:312.5,Paranoid
+#EXTART:Black Sabbath
+# a comment
+#EXTALB:War Pigs
+#EXTGRP:Metal
+/music/paranoid.mp3
+"
         );
 
-        assert!(matches!(
-            parser.all_songs(),
-            Err(ParserError::UnknownExtline(s)) if s == "#EXTNOTSUPPORTED"
-        ));
+        // The duplicates of a directive are written back, only the last one is read
+        let songs = "#EXTINF:0,A\n#EXTALB:A\n#EXTALB:B\n/music/a.mp3\n";
+        let mut parsed = Parser::from_string(songs).all_songs().unwrap();
+        assert_eq!(parsed[0].album.as_deref(), Some("B"));
+        assert_eq!(parsed[0].serialize(), songs);
+        parsed[0].album = Some("C".into());
+        assert_eq!(parsed[0].serialize(), songs.replace(":B", ":C"));
     }
 }
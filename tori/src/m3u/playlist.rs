@@ -0,0 +1,339 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    playlist_management::{lock_playlist, write_playlist, PlaylistLock},
+    relative_paths_base, Parser, Song,
+};
+use crate::{config::Config, error::Result};
+
+/// A playlist loaded in memory. Songs are edited here and the file is only written on
+/// [Playlist::save], instead of reading and parsing it again for every change.
+///
+/// The songs keep their paths as they're written in the file, so relative paths stay relative.
+/// Use [Playlist::resolved_song] to get a song the player can find.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Playlist {
+    path: PathBuf,
+    /// The `#EXTM3U` line and the `#PLAYLIST` directives after it
+    header: Vec<String>,
+    songs: Vec<Song>,
+    /// Lines after the last song, like comments, that are written back as they were
+    trailer: Vec<String>,
+    /// Whether there are changes that weren't saved yet
+    dirty: bool,
+    /// The file as it was read or last written, to find the changes made to it since. None if
+    /// it wasn't written yet.
+    saved: Option<String>,
+}
+
+impl Playlist {
+    /// Opens the playlist with the given name in the playlists directory
+    pub fn open(name: &str) -> Result<Self> {
+        Self::from_path(Config::playlist_path(name))
+    }
+
+    pub fn from_path(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let content = std::fs::read_to_string(&path)
+            .map_err(|_| format!("Couldn't open playlist file {}", path.display()))?;
+        Self::parse(path, &content)
+    }
+
+    /// An empty playlist that will be saved to `path`. Nothing is written until it's saved, even
+    /// if no songs are added to it.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            header: vec!["#EXTM3U".into()],
+            dirty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Parses the `content` of the playlist at `path`
+    pub fn parse(path: PathBuf, content: &str) -> Result<Self> {
+        let mut parser = Parser::from_string(content);
+
+        // Playlists without a header get one when they're saved
+        let header = if parser.next_header()? {
+            content[..parser.cursor()]
+                .lines()
+                .map(String::from)
+                .collect()
+        } else {
+            vec!["#EXTM3U".into()]
+        };
+
+        let mut songs = Vec::new();
+        let mut end = parser.cursor();
+        while let Some(song) = parser.next_song()? {
+            songs.push(song);
+            end = parser.cursor();
+        }
+        let trailer = content[end..]
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+
+        Ok(Self {
+            path,
+            header,
+            songs,
+            trailer,
+            dirty: false,
+            saved: Some(content.to_string()),
+        })
+    }
+
+    /// The name of the playlist, which is its file name without the extension
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The `i`-th song, with its path joined to [relative_paths_base] if it's relative, so the
+    /// player can find it
+    pub fn resolved_song(&self, i: usize) -> Option<Song> {
+        let mut song = self.songs.get(i)?.clone();
+        song.resolve_path(&relative_paths_base(&self.path));
+        Some(song)
+    }
+
+    pub fn insert(&mut self, i: usize, song: Song) {
+        self.songs.insert(i, song);
+        self.dirty = true;
+    }
+
+    /// Adds a song to the end of the playlist
+    pub fn push(&mut self, song: Song) {
+        self.insert(self.songs.len(), song);
+    }
+
+    pub fn remove(&mut self, i: usize) -> Song {
+        self.dirty = true;
+        self.songs.remove(i)
+    }
+
+    /// Moves the song at `from` to `to`, shifting the songs in between
+    pub fn move_song(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        let song = self.songs.remove(from);
+        self.songs.insert(to, song);
+        self.dirty = true;
+    }
+
+    pub fn rename_song(&mut self, i: usize, title: &str) {
+        if self.songs[i].title != title {
+            self.songs[i].title = title.to_string();
+            self.dirty = true;
+        }
+    }
+
+    /// Sorts the songs, keeping the order of the ones that compare equal
+    pub fn sort_by(&mut self, compare: impl FnMut(&Song, &Song) -> std::cmp::Ordering) {
+        self.songs.sort_by(compare);
+        self.dirty = true;
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut s = String::new();
+        for line in &self.header {
+            s += line;
+            s.push('\n');
+        }
+        s.extend(self.songs.iter().map(Song::serialize));
+        for line in &self.trailer {
+            s += line;
+            s.push('\n');
+        }
+        s
+    }
+
+    /// Writes the playlist if it has unsaved changes, holding its [lock_playlist] meanwhile
+    pub fn save(&mut self) -> Result<()> {
+        if self.dirty {
+            let lock = lock_playlist(&self.path);
+            self.save_locked(&lock)?;
+        }
+        Ok(())
+    }
+
+    /// Like [Playlist::save], when the playlist was already locked, to read it and save it
+    /// without other changes in between
+    pub fn save_locked(&mut self, _lock: &PlaylistLock) -> Result<()> {
+        if self.dirty {
+            self.keep_added_songs()?;
+            let content = self.serialize();
+            write_playlist(&self.path, &content)?;
+            self.saved = Some(content);
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Songs can be added to the file from another thread while the playlist is edited here, so
+    /// they're added here too before saving it. If the file was changed in any other way, it's
+    /// not overwritten.
+    fn keep_added_songs(&mut self) -> Result<()> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if content == self.saved {
+            return Ok(());
+        }
+
+        let path = self.path.display();
+        let (content, saved) = match (content, &self.saved) {
+            (Some(content), Some(saved)) => (content, saved),
+            (Some(_), None) => return Err(format!("Playlist {} already exists", path).into()),
+            (None, _) => return Err(format!("Playlist {} was renamed or deleted", path).into()),
+        };
+        let on_disk = Self::parse(self.path.clone(), &content)?;
+        let saved = Self::parse(self.path.clone(), saved)?;
+        match on_disk.songs.strip_prefix(saved.songs.as_slice()) {
+            Some(added) => {
+                self.songs.extend_from_slice(added);
+                Ok(())
+            }
+            None => Err(format!("Playlist {} was changed by another program", path).into()),
+        }
+    }
+
+    /// Forgets the changes that weren't saved, so they're not saved later, like when saving
+    /// failed because the file was changed by another program
+    pub fn discard_changes(&mut self) {
+        self.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROCK: &str = "\
+#EXTM3U
+#PLAYLIST:Rock
+
+#EXTINF:168,Paranoid
+/music/paranoid.mp3
+# live version
+#EXTINF:255,Back in Black
+/music/back_in_black.mp3
+#EXTINF:340,Smoke on the Water
+/music/smoke.mp3
+# the end
+";
+
+    fn titles(playlist: &Playlist) -> Vec<&str> {
+        playlist.songs().iter().map(|s| s.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let playlist = Playlist::parse("/playlists/rock.m3u8".into(), ROCK).unwrap();
+        assert_eq!(playlist.name(), "rock");
+        assert_eq!(
+            titles(&playlist),
+            ["Paranoid", "Back in Black", "Smoke on the Water"]
+        );
+        assert!(!playlist.is_dirty());
+        assert_eq!(playlist.serialize(), ROCK.replace("\n\n", "\n"));
+
+        // Lines are written back with their whitespace, even if the song changes
+        let indented =
+            "#EXTM3U\n  # live version\n#EXTINF:168,Paranoid \n/music/paranoid.mp3\n\t# the end\n";
+        let mut playlist = Playlist::parse("rock.m3u8".into(), indented).unwrap();
+        assert_eq!(playlist.serialize(), indented);
+        playlist.rename_song(0, "War Pigs");
+        assert_eq!(
+            playlist.serialize(),
+            indented.replace("#EXTINF:168,Paranoid ", "#EXTINF:168,War Pigs")
+        );
+
+        let playlist = Playlist::parse("rock.m3u8".into(), "/music/paranoid.mp3\n").unwrap();
+        assert_eq!(
+            playlist.serialize(),
+            "#EXTM3U\n#EXTINF:0,/music/paranoid.mp3\n/music/paranoid.mp3\n"
+        );
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut playlist = Playlist::parse("/playlists/rock.m3u8".into(), ROCK).unwrap();
+
+        playlist.move_song(0, 2);
+        assert_eq!(
+            titles(&playlist),
+            ["Back in Black", "Smoke on the Water", "Paranoid"]
+        );
+        assert!(playlist.is_dirty());
+
+        playlist.rename_song(2, "War Pigs");
+        let removed = playlist.remove(0);
+        assert_eq!(
+            removed.lines,
+            ["# live version", "#EXTINF:255,Back in Black"]
+        );
+        playlist.insert(
+            1,
+            Song {
+                title: "Highway to Hell".into(),
+                path: "/music/highway.mp3".into(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            titles(&playlist),
+            ["Smoke on the Water", "Highway to Hell", "War Pigs"]
+        );
+
+        playlist.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(
+            titles(&playlist),
+            ["Highway to Hell", "Smoke on the Water", "War Pigs"]
+        );
+        assert_eq!(
+            playlist.serialize(),
+            "#EXTM3U\n\
+             #PLAYLIST:Rock\n\
+             #EXTINF:0,Highway to Hell\n\
+             /music/highway.mp3\n\
+             #EXTINF:340,Smoke on the Water\n\
+             /music/smoke.mp3\n\
+             #EXTINF:168,War Pigs\n\
+             /music/paranoid.mp3\n\
+             # the end\n"
+        );
+    }
+}
@@ -1,12 +1,25 @@
 use std::{
+    collections::HashSet,
     fs,
     io::{self, Write},
     path,
     result::Result as StdResult,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
     thread,
 };
 
-use crate::{app::App, config::Config, error::Result, events::Event, m3u};
+use once_cell::sync::Lazy;
+
+use crate::{
+    app::App,
+    config::Config,
+    error::Result,
+    events::Event,
+    m3u::{self, Format, Playlist},
+};
 
 /// Adds a song to an existing playlist
 pub fn add_song(app: &mut App, playlist: &str, song_path: String) {
@@ -17,25 +30,35 @@ pub fn add_song(app: &mut App, playlist: &str, song_path: String) {
         return;
     }
 
+    // Relative paths in the playlist are relative to the playlist, not to where tori was started
+    let song_path = absolute_path(song_path);
+
     let sender = app.channel.sender.clone();
     let playlist = playlist.to_string();
     thread::spawn(move || {
-        add_song_recursively(&song_path, &playlist);
-
-        // Extract last part (separated by '/') of the song_path
-        let mut rsplit = song_path.trim_end_matches('/').rsplit('/');
-        let song = rsplit.next().unwrap_or(&song_path).to_string();
-
-        let event = Event::SongAdded { playlist, song };
+        let mut songs = Vec::new();
+        collect_songs_recursively(&song_path, &mut songs);
+        let event = match append_songs(&playlist, songs) {
+            Ok(()) => {
+                // Extract last part (separated by '/') of the song_path
+                let mut rsplit = song_path.trim_end_matches('/').rsplit('/');
+                let song = rsplit.next().unwrap_or(&song_path).to_string();
+                Event::SongAdded { playlist, song }
+            }
+            Err(e) => Event::AddSongFailed(format!(
+                "Failed to add '{}' to {}. Error: {}",
+                song_path, playlist, e
+            )),
+        };
         sender.send(event).expect("Failed to send internal event");
     });
 }
 
-/// Adds songs from some path. If the path points to a directory, it'll traverse the directory
-/// recursively, adding all songs inside it. If the path points to a file, it'll add that file.
-/// If it points to a URL, it adds the url.
+/// Collects songs from some path. If the path points to a directory, it'll traverse the directory
+/// recursively, collecting all songs inside it. If the path points to a file, it'll collect that
+/// file. If it points to a URL, it collects the url.
 /// We do not traverse symlinks, to avoid infinite loops.
-fn add_song_recursively(path: &str, playlist_name: &str) {
+fn collect_songs_recursively(path: &str, songs: &mut Vec<m3u::Song>) {
     let file = std::path::Path::new(&path);
     if file.is_dir() && !file.is_symlink() {
         let mut entries: Vec<_> = fs::read_dir(path)
@@ -52,13 +75,38 @@ fn add_song_recursively(path: &str, playlist_name: &str) {
                     path.display()
                 )
             });
-            add_song_recursively(path, playlist_name);
+            collect_songs_recursively(path, songs);
         }
     } else if !image_file(file) {
         let song = m3u::Song::from_path(path)
             .unwrap_or_else(|e| panic!("Failed to add '{}' to playlist. Error: {}", path, e));
-        song.add_to_playlist(playlist_name)
-            .unwrap_or_else(|e| panic!("Failed to add '{}' to playlist. Error: {}", path, e));
+        songs.push(song);
+    }
+}
+
+/// Appends the songs to the playlist, writing it only once. With the `relative_paths` option,
+/// their paths are written relative to [m3u::relative_paths_base]. Fails if the playlist was
+/// renamed or deleted while the songs were read, instead of creating it again.
+fn append_songs(playlist_name: &str, songs: Vec<m3u::Song>) -> Result<()> {
+    let lock = lock_playlist(&Config::playlist_path(playlist_name));
+    let mut playlist = Playlist::open(playlist_name)?;
+    let base = m3u::relative_paths_base(playlist.path());
+    for mut song in songs {
+        if Config::global().relative_paths {
+            song.relativize_path(&base);
+        }
+        playlist.push(song);
+    }
+    playlist.save_locked(&lock)
+}
+
+/// Joins a relative local path to the working directory
+fn absolute_path(path: String) -> String {
+    match std::env::current_dir() {
+        Ok(dir) if !m3u::is_url(&path) && path::Path::new(&path).is_relative() => {
+            dir.join(&path).to_string_lossy().into_owned()
+        }
+        _ => path,
     }
 }
 
@@ -110,94 +158,105 @@ pub fn create_playlist(playlist_name: &str) -> StdResult<(), CreatePlaylistError
 
     let path = Config::playlist_path(playlist_name);
 
-    // TODO: when it's stabilized, use std::fs::File::create_new
+    let lock = lock_playlist(&path);
     if path.try_exists()? {
         Err(CreatePlaylistError::PlaylistAlreadyExists)
     } else {
-        fs::File::create(path)?;
+        Playlist::new(path)
+            .save_locked(&lock)
+            .map_err(|e| io::Error::other(e.to_string()))?;
         Ok(())
     }
 }
 
-pub fn delete_song(playlist_name: &str, index: usize) -> Result<()> {
-    let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
-
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
-    }
-
-    let start_pos = parser.cursor();
-    let _song = parser.next_song()?;
-    let end_pos = parser.cursor();
+/// Paths of the playlists that are locked by [lock_playlist]
+static LOCKED: Lazy<Mutex<HashSet<path::PathBuf>>> = Lazy::new(Default::default);
+static UNLOCKED: Condvar = Condvar::new();
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&path)?;
-    file.write_all(content[..start_pos].as_bytes())?;
-    file.write_all(content[end_pos..].as_bytes())?;
-
-    Ok(())
-}
-
-pub fn rename_song(playlist_name: &str, index: usize, new_name: &str) -> Result<()> {
-    let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
+/// Keeps a playlist locked until it's dropped
+pub struct PlaylistLock(path::PathBuf);
 
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
+impl Drop for PlaylistLock {
+    fn drop(&mut self) {
+        LOCKED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.0);
+        UNLOCKED.notify_all();
     }
+}
 
-    let start_pos = parser.cursor();
-    let song = parser.next_song()?;
-    let end_pos = parser.cursor();
-
-    if let Some(mut song) = song {
-        song.title = new_name.to_string();
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&path)?;
-        file.write_all(content[..start_pos].as_bytes())?;
-        file.write_all(song.serialize().as_bytes())?;
-        file.write_all(content[end_pos..].as_bytes())?;
+/// Waits until no other thread holds the lock of the playlist at `path` and takes it. Every
+/// change to a playlist file holds it: [Playlist::save] while writing, and the changes that read
+/// a playlist, edit it and save it from the read to the save, or songs added from another thread
+/// in the meantime would be lost. Renaming and deleting a playlist hold it too, so a song being
+/// added can't bring the old playlist back.
+pub fn lock_playlist(path: &path::Path) -> PlaylistLock {
+    let mut locked = LOCKED.lock().unwrap_or_else(|e| e.into_inner());
+    while locked.contains(path) {
+        locked = UNLOCKED.wait(locked).unwrap_or_else(|e| e.into_inner());
     }
-
-    Ok(())
+    locked.insert(path.to_path_buf());
+    PlaylistLock(path.to_path_buf())
 }
 
-/// Swaps `index`-th song with the `index+1`-th (0-indexed)
-pub fn swap_song(playlist_name: &str, index: usize) -> Result<()> {
-    let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
+/// Where the previous version of the playlist at `path` is kept
+pub fn backup_path(path: &path::Path) -> path::PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    backup.into()
+}
 
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
+/// Replaces the playlist file at `path` with `content`, which every change to a playlist goes
+/// through. The content is written to a temporary file in the same directory, synced to the disk
+/// and renamed over the playlist, so a crash, a full disk or another write in the middle can't
+/// leave it half written. The previous version is kept in [backup_path].
+pub fn write_playlist(path: &path::Path, content: &str) -> Result<()> {
+    // Songs are added from other threads, so each write gets its own temporary file
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = path.parent().unwrap_or(path::Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid playlist path: {}", path.display()))?;
+    let temp = dir.join(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let write_temp = || -> io::Result<()> {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    };
+    if let Err(e) = write_temp() {
+        fs::remove_file(&temp).ok();
+        return Err(format!("Couldn't write {}: {}", path.display(), e).into());
     }
 
-    let start_pos = parser.cursor();
-    let song1 = parser.next_song()?;
-    let song2 = parser.next_song()?;
-    let end_pos = parser.cursor();
+    // The backup is a hard link when possible, so the playlist is never missing
+    if path.exists() {
+        let backup = backup_path(path);
+        match fs::remove_file(&backup) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if fs::hard_link(path, &backup).is_err() {
+            fs::copy(path, &backup)?;
+        }
+    }
 
-    if let (Some(song1), Some(song2)) = (song1, song2) {
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&path)?;
-        file.write_all(content[..start_pos].as_bytes())?;
-        file.write_all(song2.serialize().as_bytes())?;
-        file.write_all(song1.serialize().as_bytes())?;
-        file.write_all(content[end_pos..].as_bytes())?;
+    if let Err(e) = fs::rename(&temp, path) {
+        fs::remove_file(&temp).ok();
+        return Err(format!("Couldn't write {}: {}", path.display(), e).into());
     }
 
+    // Makes the rename itself durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
     Ok(())
 }
 
@@ -216,7 +275,12 @@ pub fn rename_playlist(playlist_name: &str, new_name: &str) -> StdResult<(), Ren
 
     let old_path: path::PathBuf = Config::playlist_path(playlist_name);
     let new_path: path::PathBuf = Config::playlist_path(new_name);
+    if old_path == new_path {
+        return Err(RenamePlaylistError::PlaylistAlreadyExists);
+    }
 
+    let _old_lock = lock_playlist(&old_path);
+    let _new_lock = lock_playlist(&new_path);
     if let Ok(metadata) = fs::metadata(new_path.clone()) {
         if metadata.is_file() || metadata.is_dir() {
             return Err(RenamePlaylistError::PlaylistAlreadyExists);
@@ -225,12 +289,71 @@ pub fn rename_playlist(playlist_name: &str, new_name: &str) -> StdResult<(), Ren
 
     match fs::rename(&old_path, &new_path) {
         Err(e) => Err(RenamePlaylistError::IOError(e)),
-        Ok(_) => Ok(()),
+        Ok(_) => {
+            // The backup goes with it, if there's one
+            fs::rename(backup_path(&old_path), backup_path(&new_path)).ok();
+            Ok(())
+        }
+    }
+}
+
+/// Converts the playlist at `path`, in any [Format], into a new playlist named after the file.
+/// Returns the name of the new playlist.
+pub fn import_playlist(path: &str) -> Result<String> {
+    let path = path::PathBuf::from(absolute_path(path.to_string()));
+    let format = Format::from_path(&path).ok_or_else(|| {
+        format!(
+            "Can't import '{}': playlists should end in .m3u, .m3u8, .pls, .xspf or .cue",
+            path.display()
+        )
+    })?;
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Can't import '{}': invalid file name", path.display()))?
+        .to_string();
+
+    let playlist_path = Config::playlist_path(&name);
+    let lock = lock_playlist(&playlist_path);
+    if playlist_path.try_exists()? {
+        return Err(format!("Playlist '{}' already exists!", name).into());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    let dir = path.parent().unwrap_or(path::Path::new(""));
+    let base = m3u::relative_paths_base(&playlist_path);
+    let mut playlist = Playlist::new(playlist_path);
+    for mut song in format.parse(&content, dir)? {
+        if Config::global().relative_paths {
+            song.relativize_path(&base);
+        }
+        playlist.push(song);
     }
+
+    playlist.save_locked(&lock)?;
+    Ok(name)
+}
+
+/// Writes the songs of a playlist to `path`, in the [Format] of its extension
+pub fn export_playlist(playlist_name: &str, path: &str) -> Result<()> {
+    let format = Format::from_path(path::Path::new(path)).ok_or_else(|| {
+        format!(
+            "Can't export to '{}': the file should end in .m3u, .m3u8, .pls, .xspf or .cue",
+            path
+        )
+    })?;
+
+    let playlist_path = Config::playlist_path(playlist_name);
+    let content = fs::read_to_string(&playlist_path)?;
+    let songs = Format::M3u.parse(&content, &m3u::relative_paths_base(&playlist_path))?;
+    fs::write(path, format.serialize(&songs)?)?;
+    Ok(())
 }
 
+/// The playlist is moved to its [backup_path], so it can still be recovered
 pub fn delete_playlist(playlist_name: &str) -> Result<()> {
     let path = Config::playlist_path(playlist_name);
-    fs::remove_file(path)?;
+    let _lock = lock_playlist(&path);
+    fs::rename(&path, backup_path(&path))?;
     Ok(())
 }
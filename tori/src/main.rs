@@ -4,6 +4,7 @@ use app::App;
 use argh::FromArgs;
 use config::{Config, OptionalConfig};
 pub use error::{Error, Result};
+use player::Backend;
 use std::path::{Path, PathBuf};
 
 #[derive(FromArgs)]
@@ -14,6 +15,11 @@ struct Args {
     /// $CONFIG_DIR/tori.yaml, where $CONFIG_DIR is $HOME/.config on Linux,
     /// $HOME/Library/Application Support on macOS, and %appdata% on Windows.
     config: Option<String>,
+
+    #[argh(option, short = 'b')]
+    /// the player backend: 'mpv' or 'tori-player'. Overrides the player_backend option of the
+    /// config file. By default, mpv is used if it's available, and tori-player otherwise.
+    backend: Option<Backend>,
 }
 
 fn main() -> Result<()> {
@@ -27,7 +33,11 @@ fn main() -> Result<()> {
                 .unwrap_or(dirs::config_dir().unwrap_or_default().join("tori.yaml")),
         )?;
 
-        Config::default().merge(opt_conf)
+        let mut config = Config::default().merge(opt_conf);
+        if args.backend.is_some() {
+            config.player_backend = args.backend;
+        }
+        config
     });
 
     make_sure_playlist_dir_exists();
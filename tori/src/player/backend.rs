@@ -0,0 +1,298 @@
+use std::{fmt, str::FromStr, sync::mpsc};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{Chapter, Player, RepeatMode};
+use crate::{config::Config, error::Result, events::Event};
+
+#[cfg(not(any(feature = "mpv", feature = "tori-player")))]
+compile_error!("tori needs at least one player backend: enable the `mpv` or `tori-player` feature");
+
+/// The player backends tori can be compiled with. Chosen with the `player_backend` config key or
+/// the `--backend` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    Mpv,
+    ToriPlayer,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "mpv" => Ok(Self::Mpv),
+            "tori-player" => Ok(Self::ToriPlayer),
+            _ => Err(format!(
+                "Unknown player backend '{}'. Expected 'mpv' or 'tori-player'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mpv => write!(f, "mpv"),
+            Self::ToriPlayer => write!(f, "tori-player"),
+        }
+    }
+}
+
+/// A [Player] whose backend is chosen at runtime, out of the ones tori was compiled with
+pub enum AnyPlayer {
+    #[cfg(feature = "mpv")]
+    Mpv(super::mpv::MpvPlayer),
+    #[cfg(feature = "tori-player")]
+    ToriPlayer(tori_player::Player),
+    #[cfg(test)]
+    Mock(super::mock::MockPlayer),
+}
+
+impl AnyPlayer {
+    pub fn with_backend(backend: Backend) -> Result<Self> {
+        match backend {
+            #[cfg(feature = "mpv")]
+            Backend::Mpv => Ok(Self::Mpv(Player::new()?)),
+            #[cfg(feature = "tori-player")]
+            Backend::ToriPlayer => Ok(Self::ToriPlayer(Player::new()?)),
+            #[allow(unreachable_patterns)]
+            backend => Err(format!("tori was compiled without the {} backend", backend).into()),
+        }
+    }
+}
+
+/// Calls the same method on whichever backend is in use
+macro_rules! dispatch {
+    ($self:ident, $player:ident => $call:expr) => {
+        match $self {
+            #[cfg(feature = "mpv")]
+            AnyPlayer::Mpv($player) => $call,
+            #[cfg(feature = "tori-player")]
+            AnyPlayer::ToriPlayer($player) => $call,
+            #[cfg(test)]
+            AnyPlayer::Mock($player) => $call,
+        }
+    };
+}
+
+impl Player for AnyPlayer {
+    /// Uses the backend in the config. If there's none, mpv is preferred, and tori-player is used
+    /// when mpv can't be initialized, like when libmpv isn't installed.
+    fn new() -> Result<Self> {
+        if let Some(backend) = Config::global().player_backend {
+            return Self::with_backend(backend);
+        }
+
+        Self::with_backend(Backend::Mpv).or_else(|e| {
+            warn!("Couldn't start mpv ({}), falling back to tori-player", e);
+            Self::with_backend(Backend::ToriPlayer)
+        })
+    }
+
+    fn play(&mut self, path: &str) -> Result<()> {
+        dispatch!(self, p => p.play(path))
+    }
+
+    fn queue(&mut self, path: &str) -> Result<()> {
+        dispatch!(self, p => p.queue(path))
+    }
+
+    fn queue_next(&mut self, path: &str) -> Result<()> {
+        dispatch!(self, p => p.queue_next(path))
+    }
+
+    fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()> {
+        dispatch!(self, p => p.queue_at(path, seconds))
+    }
+
+    fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        dispatch!(self, p => p.play_section(path, start, end))
+    }
+
+    fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        dispatch!(self, p => p.queue_section(path, start, end))
+    }
+
+    fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()> {
+        dispatch!(self, p => p.queue_section_at(path, start, end, seconds))
+    }
+
+    fn seek(&mut self, seconds: f64) -> Result<()> {
+        dispatch!(self, p => p.seek(seconds))
+    }
+
+    fn seek_absolute(&mut self, percent: usize) -> Result<()> {
+        dispatch!(self, p => p.seek_absolute(percent))
+    }
+
+    fn playlist_next(&mut self) -> Result<()> {
+        dispatch!(self, p => p.playlist_next())
+    }
+
+    fn playlist_previous(&mut self) -> Result<()> {
+        dispatch!(self, p => p.playlist_previous())
+    }
+
+    fn toggle_pause(&mut self) -> Result<()> {
+        dispatch!(self, p => p.toggle_pause())
+    }
+
+    fn set_repeat(&mut self, mode: RepeatMode) -> Result<()> {
+        dispatch!(self, p => p.set_repeat(mode))
+    }
+
+    fn repeat_mode(&self) -> Result<RepeatMode> {
+        dispatch!(self, p => p.repeat_mode())
+    }
+
+    fn volume(&self) -> Result<i64> {
+        dispatch!(self, p => p.volume())
+    }
+
+    fn add_volume(&mut self, x: isize) -> Result<()> {
+        dispatch!(self, p => p.add_volume(x))
+    }
+
+    fn set_volume(&mut self, x: i64) -> Result<()> {
+        dispatch!(self, p => p.set_volume(x))
+    }
+
+    fn toggle_mute(&mut self) -> Result<()> {
+        dispatch!(self, p => p.toggle_mute())
+    }
+
+    fn muted(&self) -> Result<bool> {
+        dispatch!(self, p => p.muted())
+    }
+
+    fn speed(&self) -> Result<f64> {
+        dispatch!(self, p => p.speed())
+    }
+
+    fn set_speed(&mut self, speed: f64) -> Result<()> {
+        dispatch!(self, p => p.set_speed(speed))
+    }
+
+    fn media_title(&self) -> Result<String> {
+        dispatch!(self, p => p.media_title())
+    }
+
+    fn percent_pos(&self) -> Result<i64> {
+        dispatch!(self, p => p.percent_pos())
+    }
+
+    fn time_pos(&self) -> Result<i64> {
+        dispatch!(self, p => p.time_pos())
+    }
+
+    fn precise_time_pos(&self) -> Result<f64> {
+        dispatch!(self, p => p.precise_time_pos())
+    }
+
+    fn time_remaining(&self) -> Result<i64> {
+        dispatch!(self, p => p.time_remaining())
+    }
+
+    fn paused(&self) -> Result<bool> {
+        dispatch!(self, p => p.paused())
+    }
+
+    fn playlist_count(&self) -> Result<usize> {
+        dispatch!(self, p => p.playlist_count())
+    }
+
+    fn playlist_track_title(&self, i: usize) -> Result<String> {
+        dispatch!(self, p => p.playlist_track_title(i))
+    }
+
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        dispatch!(self, p => p.playlist_track_path(i))
+    }
+
+    fn playlist_track_section(&self, i: usize) -> Result<Option<(f64, Option<f64>)>> {
+        dispatch!(self, p => p.playlist_track_section(i))
+    }
+
+    fn playlist_position(&self) -> Result<usize> {
+        dispatch!(self, p => p.playlist_position())
+    }
+
+    fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        dispatch!(self, p => p.playlist_move(from, to))
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        dispatch!(self, p => p.playlist_remove(i))
+    }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        dispatch!(self, p => p.playlist_play_index(i))
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        dispatch!(self, p => p.playlist_clear())
+    }
+
+    fn chapters(&self) -> Result<Vec<Chapter>> {
+        dispatch!(self, p => p.chapters())
+    }
+
+    fn chapter(&self) -> Result<Option<usize>> {
+        dispatch!(self, p => p.chapter())
+    }
+
+    fn set_chapter(&mut self, i: usize) -> Result<()> {
+        dispatch!(self, p => p.set_chapter(i))
+    }
+
+    fn ab_loop(&self) -> Result<(Option<f64>, Option<f64>)> {
+        dispatch!(self, p => p.ab_loop())
+    }
+
+    fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()> {
+        dispatch!(self, p => p.set_ab_loop(a, b))
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>> {
+        dispatch!(self, p => p.output_devices())
+    }
+
+    fn output_device(&self) -> Result<String> {
+        dispatch!(self, p => p.output_device())
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        dispatch!(self, p => p.set_output_device(device))
+    }
+
+    fn forward_events(&mut self, sender: mpsc::Sender<Event>) -> Result<()> {
+        dispatch!(self, p => p.forward_events(sender))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_names() {
+        for backend in [Backend::Mpv, Backend::ToriPlayer] {
+            assert_eq!(backend.to_string().parse(), Ok(backend));
+            assert_eq!(
+                serde_yaml::from_str::<Backend>(&backend.to_string()).ok(),
+                Some(backend)
+            );
+        }
+        assert!("vlc".parse::<Backend>().is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use std::{path::Path, sync::mpsc};
+
+use super::{Chapter, Player, RepeatMode};
+use crate::{error::Result, events::Event};
+
+/// A call that changed the state of a [MockPlayer]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    Play(String),
+    Queue(String),
+    QueueNext(String),
+    QueueAt(String, f64),
+    PlaySection(String, f64, Option<f64>),
+    QueueSection(String, f64, Option<f64>),
+    QueueSectionAt(String, f64, Option<f64>, f64),
+    Seek(f64),
+    SeekAbsolute(usize),
+    PlaylistNext,
+    PlaylistPrevious,
+    TogglePause,
+    SetRepeat(RepeatMode),
+    AddVolume(isize),
+    SetVolume(i64),
+    ToggleMute,
+    SetSpeed(f64),
+    PlaylistMove(usize, usize),
+    PlaylistRemove(usize),
+    PlaylistPlayIndex(usize),
+    PlaylistClear,
+    SetChapter(usize),
+    SetAbLoop(Option<f64>, Option<f64>),
+    SetOutputDevice(String),
+}
+
+/// A [Player] that doesn't play anything. It records the calls it gets and keeps a playlist, so
+/// tests can check what the app asked the player to do.
+#[derive(Debug)]
+pub struct MockPlayer {
+    pub calls: Vec<Call>,
+    pub playlist: Vec<String>,
+    /// The sections of the songs in the playlist, for the ones that were queued as one
+    pub sections: Vec<Option<(f64, Option<f64>)>>,
+    pub position: Option<usize>,
+    pub paused: bool,
+    pub repeat: RepeatMode,
+    pub volume: i64,
+    pub muted: bool,
+    pub speed: f64,
+    /// Playback position of the current song, in seconds
+    pub time_pos: f64,
+    /// Chapters of every song, and the one being played
+    pub chapters: Vec<Chapter>,
+    pub chapter: Option<usize>,
+    pub ab_loop: (Option<f64>, Option<f64>),
+    pub device: String,
+}
+
+impl Default for MockPlayer {
+    fn default() -> Self {
+        Self {
+            calls: Vec::new(),
+            playlist: Vec::new(),
+            sections: Vec::new(),
+            position: None,
+            paused: false,
+            repeat: RepeatMode::Off,
+            volume: 100,
+            muted: false,
+            speed: 1.0,
+            time_pos: 0.0,
+            chapters: Vec::new(),
+            chapter: None,
+            ab_loop: (None, None),
+            device: "auto".into(),
+        }
+    }
+}
+
+impl Player for MockPlayer {
+    fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn play(&mut self, path: &str) -> Result<()> {
+        self.calls.push(Call::Play(path.into()));
+        self.playlist = vec![path.into()];
+        self.sections = vec![None];
+        self.position = Some(0);
+        self.paused = false;
+        Ok(())
+    }
+
+    fn queue(&mut self, path: &str) -> Result<()> {
+        self.calls.push(Call::Queue(path.into()));
+        self.playlist.push(path.into());
+        self.sections.push(None);
+        self.position.get_or_insert(0);
+        Ok(())
+    }
+
+    fn queue_next(&mut self, path: &str) -> Result<()> {
+        self.calls.push(Call::QueueNext(path.into()));
+        match self.position {
+            Some(i) => {
+                self.playlist.insert(i + 1, path.into());
+                self.sections.insert(i + 1, None);
+            }
+            None => {
+                self.playlist.push(path.into());
+                self.sections.push(None);
+                self.position = Some(self.playlist.len() - 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()> {
+        self.calls.push(Call::QueueAt(path.into(), seconds));
+        self.playlist.push(path.into());
+        self.sections.push(None);
+        self.position.get_or_insert(0);
+        Ok(())
+    }
+
+    fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.calls.push(Call::PlaySection(path.into(), start, end));
+        self.playlist = vec![path.into()];
+        self.sections = vec![Some((start, end))];
+        self.position = Some(0);
+        self.paused = false;
+        Ok(())
+    }
+
+    fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.calls.push(Call::QueueSection(path.into(), start, end));
+        self.playlist.push(path.into());
+        self.sections.push(Some((start, end)));
+        self.position.get_or_insert(0);
+        Ok(())
+    }
+
+    fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()> {
+        self.calls
+            .push(Call::QueueSectionAt(path.into(), start, end, seconds));
+        self.playlist.push(path.into());
+        self.sections.push(Some((start, end)));
+        self.position.get_or_insert(0);
+        Ok(())
+    }
+
+    fn seek(&mut self, seconds: f64) -> Result<()> {
+        self.calls.push(Call::Seek(seconds));
+        Ok(())
+    }
+
+    fn seek_absolute(&mut self, percent: usize) -> Result<()> {
+        self.calls.push(Call::SeekAbsolute(percent));
+        Ok(())
+    }
+
+    fn playlist_next(&mut self) -> Result<()> {
+        self.calls.push(Call::PlaylistNext);
+        match self.position {
+            Some(i) if i + 1 < self.playlist.len() => {
+                self.position = Some(i + 1);
+                Ok(())
+            }
+            _ => Err("No next song".into()),
+        }
+    }
+
+    fn playlist_previous(&mut self) -> Result<()> {
+        self.calls.push(Call::PlaylistPrevious);
+        match self.position {
+            Some(i) if i > 0 => {
+                self.position = Some(i - 1);
+                Ok(())
+            }
+            _ => Err("No previous song".into()),
+        }
+    }
+
+    fn toggle_pause(&mut self) -> Result<()> {
+        self.calls.push(Call::TogglePause);
+        self.paused = !self.paused;
+        Ok(())
+    }
+
+    fn set_repeat(&mut self, mode: RepeatMode) -> Result<()> {
+        self.calls.push(Call::SetRepeat(mode));
+        self.repeat = mode;
+        Ok(())
+    }
+
+    fn repeat_mode(&self) -> Result<RepeatMode> {
+        Ok(self.repeat)
+    }
+
+    fn volume(&self) -> Result<i64> {
+        Ok(self.volume)
+    }
+
+    fn add_volume(&mut self, x: isize) -> Result<()> {
+        self.calls.push(Call::AddVolume(x));
+        self.volume = (self.volume + x as i64).clamp(0, 100);
+        Ok(())
+    }
+
+    fn set_volume(&mut self, x: i64) -> Result<()> {
+        self.calls.push(Call::SetVolume(x));
+        self.volume = x.clamp(0, 100);
+        Ok(())
+    }
+
+    fn toggle_mute(&mut self) -> Result<()> {
+        self.calls.push(Call::ToggleMute);
+        self.muted = !self.muted;
+        Ok(())
+    }
+
+    fn muted(&self) -> Result<bool> {
+        Ok(self.muted)
+    }
+
+    fn speed(&self) -> Result<f64> {
+        Ok(self.speed)
+    }
+
+    fn set_speed(&mut self, speed: f64) -> Result<()> {
+        self.calls.push(Call::SetSpeed(speed));
+        self.speed = speed;
+        Ok(())
+    }
+
+    fn media_title(&self) -> Result<String> {
+        let i = self.playlist_position()?;
+        self.playlist_track_title(i)
+    }
+
+    fn percent_pos(&self) -> Result<i64> {
+        Ok(0)
+    }
+
+    fn time_pos(&self) -> Result<i64> {
+        Ok(self.time_pos as i64)
+    }
+
+    fn precise_time_pos(&self) -> Result<f64> {
+        Ok(self.time_pos)
+    }
+
+    fn time_remaining(&self) -> Result<i64> {
+        Ok(0)
+    }
+
+    fn paused(&self) -> Result<bool> {
+        Ok(self.paused)
+    }
+
+    fn playlist_count(&self) -> Result<usize> {
+        Ok(self.playlist.len())
+    }
+
+    fn playlist_track_title(&self, i: usize) -> Result<String> {
+        let path = self.playlist.get(i).ok_or("No such song in the playlist")?;
+        Ok(Path::new(path)
+            .file_stem()
+            .map_or_else(|| path.clone(), |stem| stem.to_string_lossy().into_owned()))
+    }
+
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        Ok(self
+            .playlist
+            .get(i)
+            .ok_or("No such song in the playlist")?
+            .clone())
+    }
+
+    fn playlist_track_section(&self, i: usize) -> Result<Option<(f64, Option<f64>)>> {
+        self.playlist_track_path(i)?;
+        // Tests may set the playlist without the sections
+        Ok(self.sections.get(i).copied().flatten())
+    }
+
+    fn playlist_position(&self) -> Result<usize> {
+        Ok(self.position.ok_or("Nothing is playing")?)
+    }
+
+    fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        self.calls.push(Call::PlaylistMove(from, to));
+        if from >= self.playlist.len() || to >= self.playlist.len() {
+            return Err("No such song in the playlist".into());
+        }
+        let song = self.playlist.remove(from);
+        self.playlist.insert(to, song);
+        if from < self.sections.len() && to < self.sections.len() {
+            let section = self.sections.remove(from);
+            self.sections.insert(to, section);
+        }
+        self.position = self.position.map(|i| match i {
+            i if i == from => to,
+            i if from < i && i <= to => i - 1,
+            i if to <= i && i < from => i + 1,
+            i => i,
+        });
+        Ok(())
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        self.calls.push(Call::PlaylistRemove(i));
+        if i >= self.playlist.len() {
+            return Err("No such song in the playlist".into());
+        }
+        self.playlist.remove(i);
+        if i < self.sections.len() {
+            self.sections.remove(i);
+        }
+        self.position = match self.position {
+            Some(p) if p > i => Some(p - 1),
+            Some(p) if p == i && i == self.playlist.len() => None,
+            position => position,
+        };
+        Ok(())
+    }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        self.calls.push(Call::PlaylistPlayIndex(i));
+        if i >= self.playlist.len() {
+            return Err("No such song in the playlist".into());
+        }
+        self.position = Some(i);
+        Ok(())
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        self.calls.push(Call::PlaylistClear);
+        match self.position {
+            Some(p) => {
+                self.playlist = vec![self.playlist.swap_remove(p)];
+                self.sections = vec![self.sections.get(p).copied().flatten()];
+                self.position = Some(0);
+            }
+            None => {
+                self.playlist.clear();
+                self.sections.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn chapters(&self) -> Result<Vec<Chapter>> {
+        Ok(self.chapters.clone())
+    }
+
+    fn chapter(&self) -> Result<Option<usize>> {
+        Ok(self.chapter)
+    }
+
+    fn set_chapter(&mut self, i: usize) -> Result<()> {
+        self.calls.push(Call::SetChapter(i));
+        if i >= self.chapters.len() {
+            return Err("No such chapter".into());
+        }
+        self.chapter = Some(i);
+        Ok(())
+    }
+
+    fn ab_loop(&self) -> Result<(Option<f64>, Option<f64>)> {
+        Ok(self.ab_loop)
+    }
+
+    fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()> {
+        self.calls.push(Call::SetAbLoop(a, b));
+        self.ab_loop = (a, b);
+        Ok(())
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>> {
+        Ok(vec!["auto".into(), "null".into()])
+    }
+
+    fn output_device(&self) -> Result<String> {
+        Ok(self.device.clone())
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        self.calls.push(Call::SetOutputDevice(device.into()));
+        self.device = device.into();
+        Ok(())
+    }
+
+    // Tests send the player events themselves
+    fn forward_events(&mut self, _sender: mpsc::Sender<Event>) -> Result<()> {
+        Ok(())
+    }
+}
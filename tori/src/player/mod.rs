@@ -1,40 +1,153 @@
-use crate::error::Result;
+use std::sync::mpsc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, events::Event};
+
+mod backend;
+pub use backend::{AnyPlayer, Backend};
+
+pub mod shuffle;
 
 #[cfg(feature = "mpv")]
 mod mpv;
-#[cfg(feature = "mpv")]
-pub type DefaultPlayer = mpv::MpvPlayer;
 
 #[cfg(feature = "tori-player")]
 mod tori_player_glue;
-#[cfg(feature = "tori-player")]
-pub type DefaultPlayer = tori_player::Player;
+
+#[cfg(test)]
+pub mod mock;
+
+/// What the player does when a song ends
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    /// Play the next song, and stop after the last one
+    #[default]
+    Off,
+    /// Play the same song again
+    One,
+    /// Play the next song, and go back to the first one after the last
+    All,
+}
+
+impl RepeatMode {
+    /// The mode that comes after this one when cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+}
+
+/// A part of a song, like a chapter of an audiobook or a track of a mix
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    /// Where the chapter starts, in seconds
+    pub time: f64,
+}
 
 pub trait Player: Sized {
     fn new() -> Result<Self>;
     fn play(&mut self, path: &str) -> Result<()>;
     fn queue(&mut self, path: &str) -> Result<()>;
+    /// Inserts the song right after the one being played, or queues it if nothing is playing
+    fn queue_next(&mut self, path: &str) -> Result<()>;
+    /// Queues the song so it starts `seconds` into it instead of at the beginning
+    fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()>;
+    /// Plays the song only from `start` to `end`, in seconds, or to its end if there's none. Used
+    /// for the tracks of CUE sheets.
+    fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()>;
+    /// Queues the song to be played from `start` to `end`, like [Player::play_section]
+    fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()>;
+    /// Like [Player::queue_section], but the song starts `seconds` into its file, which is in the
+    /// section, instead of at `start`
+    fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()>;
     fn seek(&mut self, seconds: f64) -> Result<()>;
     fn seek_absolute(&mut self, percent: usize) -> Result<()>;
     fn playlist_next(&mut self) -> Result<()>;
     fn playlist_previous(&mut self) -> Result<()>;
     fn toggle_pause(&mut self) -> Result<()>;
-    fn toggle_loop_file(&mut self) -> Result<()>;
-    fn looping_file(&self) -> Result<bool>;
+    fn set_repeat(&mut self, mode: RepeatMode) -> Result<()>;
+    fn repeat_mode(&self) -> Result<RepeatMode>;
     fn volume(&self) -> Result<i64>;
     fn add_volume(&mut self, x: isize) -> Result<()>;
     fn set_volume(&mut self, x: i64) -> Result<()>;
     fn toggle_mute(&mut self) -> Result<()>;
     fn muted(&self) -> Result<bool>;
+    /// Playback speed, where 1.0 is the normal speed
+    fn speed(&self) -> Result<f64>;
+    /// Changes the playback speed without changing the pitch
+    fn set_speed(&mut self, speed: f64) -> Result<()>;
     fn media_title(&self) -> Result<String>;
     fn percent_pos(&self) -> Result<i64>;
     fn time_pos(&self) -> Result<i64>;
+    /// Like [Player::time_pos], but with the fraction of a second
+    fn precise_time_pos(&self) -> Result<f64>;
     fn time_remaining(&self) -> Result<i64>;
     fn paused(&self) -> Result<bool>;
-    fn shuffle(&mut self) -> Result<()>;
 
     // Playlist-related:
     fn playlist_count(&self) -> Result<usize>;
     fn playlist_track_title(&self, i: usize) -> Result<String>;
+    fn playlist_track_path(&self, i: usize) -> Result<String>;
+    /// Where the song at index `i` starts and ends in its file, if it was queued as a section
+    fn playlist_track_section(&self, i: usize) -> Result<Option<(f64, Option<f64>)>>;
     fn playlist_position(&self) -> Result<usize>;
+    /// Moves the song at index `from` so its index becomes `to`, without interrupting the song
+    /// that's playing
+    fn playlist_move(&mut self, from: usize, to: usize) -> Result<()>;
+    /// Removes the song at index `i`. If it's the one being played, the next one starts playing.
+    fn playlist_remove(&mut self, i: usize) -> Result<()>;
+    fn playlist_play_index(&mut self, i: usize) -> Result<()>;
+    /// Removes every song, except the one being played
+    fn playlist_clear(&mut self) -> Result<()>;
+
+    // Chapters:
+    /// The chapters of the current song, in order. Empty if it has none.
+    fn chapters(&self) -> Result<Vec<Chapter>>;
+    /// Index of the chapter being played, if the song has chapters and one started already
+    fn chapter(&self) -> Result<Option<usize>>;
+    /// Jumps to the start of the `i`-th chapter
+    fn set_chapter(&mut self, i: usize) -> Result<()>;
+
+    fn chapter_next(&mut self) -> Result<()> {
+        let next = self.chapter()?.map_or(0, |i| i + 1);
+        if next >= self.chapters()?.len() {
+            return Err("No next chapter".into());
+        }
+        self.set_chapter(next)
+    }
+
+    fn chapter_prev(&mut self) -> Result<()> {
+        match self.chapter()? {
+            Some(i) if i > 0 => self.set_chapter(i - 1),
+            _ => Err("No previous chapter".into()),
+        }
+    }
+
+    // A/B loop:
+    /// The A and B points of the A/B loop, in seconds. Once both are set, the player goes back to
+    /// A whenever it reaches B.
+    fn ab_loop(&self) -> Result<(Option<f64>, Option<f64>)>;
+    fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()>;
+
+    // Output devices:
+    fn output_devices(&self) -> Result<Vec<String>>;
+    fn output_device(&self) -> Result<String>;
+    fn set_output_device(&mut self, device: &str) -> Result<()>;
+
+    /// Starts sending an [Event] to `sender` whenever the state of the player changes (song, pause,
+    /// volume...) or something goes wrong in the background, so the UI doesn't have to poll for
+    /// it. Errors from the other methods are returned directly.
+    fn forward_events(&mut self, sender: mpsc::Sender<Event>) -> Result<()>;
 }
@@ -0,0 +1,253 @@
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+use super::ffi::{self, mpv_format, mpv_handle, Lib};
+use crate::error::Result;
+
+/// The values that can be read from and written to mpv's properties
+trait Data: Sized {
+    const FORMAT: mpv_format;
+
+    /// Calls `f` with a pointer to the value in mpv's format
+    fn with_ptr<T>(&self, f: impl FnOnce(*mut c_void) -> Result<T>) -> Result<T>;
+
+    /// Reads a value in mpv's format by passing a pointer to `get`, which fills it
+    fn read(lib: &Lib, get: impl FnOnce(*mut c_void) -> Result<()>) -> Result<Self>;
+}
+
+impl Data for bool {
+    const FORMAT: mpv_format = ffi::MPV_FORMAT_FLAG;
+
+    fn with_ptr<T>(&self, f: impl FnOnce(*mut c_void) -> Result<T>) -> Result<T> {
+        let mut flag = *self as c_int;
+        f(&mut flag as *mut c_int as *mut c_void)
+    }
+
+    fn read(_: &Lib, get: impl FnOnce(*mut c_void) -> Result<()>) -> Result<Self> {
+        let mut flag: c_int = 0;
+        get(&mut flag as *mut c_int as *mut c_void)?;
+        Ok(flag != 0)
+    }
+}
+
+impl Data for i64 {
+    const FORMAT: mpv_format = ffi::MPV_FORMAT_INT64;
+
+    fn with_ptr<T>(&self, f: impl FnOnce(*mut c_void) -> Result<T>) -> Result<T> {
+        let mut x = *self;
+        f(&mut x as *mut i64 as *mut c_void)
+    }
+
+    fn read(_: &Lib, get: impl FnOnce(*mut c_void) -> Result<()>) -> Result<Self> {
+        let mut x: i64 = 0;
+        get(&mut x as *mut i64 as *mut c_void)?;
+        Ok(x)
+    }
+}
+
+impl Data for f64 {
+    const FORMAT: mpv_format = ffi::MPV_FORMAT_DOUBLE;
+
+    fn with_ptr<T>(&self, f: impl FnOnce(*mut c_void) -> Result<T>) -> Result<T> {
+        let mut x = *self;
+        f(&mut x as *mut f64 as *mut c_void)
+    }
+
+    fn read(_: &Lib, get: impl FnOnce(*mut c_void) -> Result<()>) -> Result<Self> {
+        let mut x: f64 = 0.0;
+        get(&mut x as *mut f64 as *mut c_void)?;
+        Ok(x)
+    }
+}
+
+impl Data for String {
+    const FORMAT: mpv_format = ffi::MPV_FORMAT_STRING;
+
+    /// mpv takes a pointer to the `char *`
+    fn with_ptr<T>(&self, f: impl FnOnce(*mut c_void) -> Result<T>) -> Result<T> {
+        let s = CString::new(self.as_str())?;
+        let mut ptr = s.as_ptr();
+        f(&mut ptr as *mut *const c_char as *mut c_void)
+    }
+
+    /// The string is allocated by mpv, which has to free it
+    fn read(lib: &Lib, get: impl FnOnce(*mut c_void) -> Result<()>) -> Result<Self> {
+        let mut ptr: *mut c_char = std::ptr::null_mut();
+        get(&mut ptr as *mut *mut c_char as *mut c_void)?;
+        let s = unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { (lib.mpv_free)(ptr.cast()) };
+        Ok(s)
+    }
+}
+
+/// A handle to an mpv core, through a libmpv that's loaded at runtime. Works with mpv v0.34 and
+/// v0.35 alike.
+pub struct Mpv {
+    lib: &'static Lib,
+    ctx: *mut mpv_handle,
+}
+
+impl Mpv {
+    /// Creates an mpv core and calls `init` to set its options before initializing it. Fails when
+    /// libmpv isn't installed.
+    pub fn with_initializer<F>(init: F) -> Result<Self>
+    where
+        F: FnOnce(MpvInitializer) -> Result<()>,
+    {
+        let lib = ffi::lib()?;
+        let ctx = unsafe { (lib.mpv_create)() };
+        if ctx.is_null() {
+            return Err("Failed to create an mpv instance".into());
+        }
+
+        let initialized = init(MpvInitializer { lib, ctx })
+            .and_then(|()| check(lib, unsafe { (lib.mpv_initialize)(ctx) }));
+        if let Err(e) = initialized {
+            unsafe { (lib.mpv_terminate_destroy)(ctx) };
+            return Err(e);
+        }
+        Ok(Self { lib, ctx })
+    }
+
+    pub fn play(&self, path: &str) -> Result<()> {
+        self.loadfile(path, "replace", "")
+    }
+
+    pub fn queue(&self, path: &str) -> Result<()> {
+        self.loadfile(path, "append-play", "")
+    }
+
+    /// Replaces the playlist with `path`, with per-file options like `start=10`
+    pub fn play_with_options(&self, path: &str, options: &str) -> Result<()> {
+        self.loadfile(path, "replace", options)
+    }
+
+    /// Queues `path` with per-file options, like `start=10`
+    pub fn queue_with_options(&self, path: &str, options: &str) -> Result<()> {
+        self.loadfile(path, "append-play", options)
+    }
+
+    fn loadfile(&self, path: &str, mode: &str, options: &str) -> Result<()> {
+        self.command("loadfile", &[&format!("\"{}\"", path), mode, options])
+    }
+
+    /// The raw handle of the mpv core, for the parts of the client API this wrapper doesn't cover
+    pub fn handle(&self) -> *mut mpv_handle {
+        self.ctx
+    }
+
+    pub fn seek_forward(&self, s: f64) -> Result<()> {
+        self.command("seek", &[&format!("{}", s), "relative"])
+    }
+
+    pub fn seek_backward(&self, s: f64) -> Result<()> {
+        self.command("seek", &[&format!("-{}", s), "relative"])
+    }
+
+    pub fn playlist_next_weak(&self) -> Result<()> {
+        self.command("playlist-next", &["weak"])
+    }
+
+    pub fn playlist_previous_weak(&self) -> Result<()> {
+        self.command("playlist-prev", &["weak"])
+    }
+
+    /// Runs a command in mpv's input.conf syntax, so the arguments are split by spaces
+    pub fn command(&self, name: &str, args: &[&str]) -> Result<()> {
+        let mut cmd = name.to_owned();
+        for arg in args {
+            cmd.push(' ');
+            cmd.push_str(arg);
+        }
+        let cmd = CString::new(cmd)?;
+        check(self.lib, unsafe {
+            (self.lib.mpv_command_string)(self.ctx, cmd.as_ptr())
+        })
+    }
+
+    pub fn get_bool(&self, name: &str) -> Result<bool> {
+        get_property(self.lib, self.ctx, name)
+    }
+
+    pub fn get_str(&self, name: &str) -> Result<String> {
+        get_property(self.lib, self.ctx, name)
+    }
+
+    pub fn get_i64(&self, name: &str) -> Result<i64> {
+        get_property(self.lib, self.ctx, name)
+    }
+
+    pub fn get_f64(&self, name: &str) -> Result<f64> {
+        get_property(self.lib, self.ctx, name)
+    }
+
+    pub fn set_str(&self, name: &str, data: &str) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data.to_owned())
+    }
+
+    pub fn set_i64(&self, name: &str, data: i64) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data)
+    }
+
+    pub fn set_f64(&self, name: &str, data: f64) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data)
+    }
+
+    pub fn add_isize(&self, name: &str, data: isize) -> Result<()> {
+        self.command("add", &[name, &format!("{}", data)])
+    }
+}
+
+impl Drop for Mpv {
+    fn drop(&mut self) {
+        unsafe { (self.lib.mpv_terminate_destroy)(self.ctx) };
+    }
+}
+
+/// Sets the options of an mpv core before it's initialized
+pub struct MpvInitializer {
+    lib: &'static Lib,
+    ctx: *mut mpv_handle,
+}
+
+impl MpvInitializer {
+    pub fn set_bool(&self, name: &str, data: bool) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data)
+    }
+
+    pub fn set_i64(&self, name: &str, data: i64) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data)
+    }
+
+    pub fn set_str(&self, name: &str, data: &str) -> Result<()> {
+        set_property(self.lib, self.ctx, name, &data.to_owned())
+    }
+}
+
+fn get_property<T: Data>(lib: &Lib, ctx: *mut mpv_handle, name: &str) -> Result<T> {
+    let name = CString::new(name)?;
+    T::read(lib, |data| {
+        check(lib, unsafe {
+            (lib.mpv_get_property)(ctx, name.as_ptr(), T::FORMAT, data)
+        })
+    })
+}
+
+fn set_property<T: Data>(lib: &Lib, ctx: *mut mpv_handle, name: &str, data: &T) -> Result<()> {
+    let name = CString::new(name)?;
+    data.with_ptr(|data| {
+        check(lib, unsafe {
+            (lib.mpv_set_property)(ctx, name.as_ptr(), T::FORMAT, data)
+        })
+    })
+}
+
+/// Turns mpv's negative error codes into errors
+fn check(lib: &Lib, err: c_int) -> Result<()> {
+    if err >= 0 {
+        return Ok(());
+    }
+    let msg = unsafe { CStr::from_ptr((lib.mpv_error_string)(err)) };
+    Err(format!("mpv error: {}", msg.to_string_lossy()).into())
+}
@@ -0,0 +1,132 @@
+//! Observes mpv's properties and forwards their changes as [Event]s.
+//!
+//! Waiting for events blocks the client that waits, so a second client of the same mpv core is
+//! created, and its events are received in a thread of its own.
+
+use std::{
+    ffi::{c_void, CStr},
+    sync::mpsc,
+    thread,
+};
+
+use super::ffi::{self, *};
+use crate::{error::Result, events::Event};
+
+// Identifiers of the observed properties, as in `reply_userdata`
+const PAUSE: u64 = 1;
+const MEDIA_TITLE: u64 = 2;
+const PLAYLIST_POS: u64 = 3;
+const VOLUME: u64 = 4;
+const MUTE: u64 = 5;
+const PLAYLIST_COUNT: u64 = 6;
+
+const OBSERVED: [(u64, &CStr, mpv_format); 6] = [
+    (PAUSE, c"pause", MPV_FORMAT_FLAG),
+    (MEDIA_TITLE, c"media-title", MPV_FORMAT_NONE),
+    (PLAYLIST_POS, c"playlist-pos", MPV_FORMAT_NONE),
+    (VOLUME, c"volume", MPV_FORMAT_NONE),
+    (MUTE, c"mute", MPV_FORMAT_NONE),
+    (PLAYLIST_COUNT, c"playlist-count", MPV_FORMAT_NONE),
+];
+
+/// An mpv client that's only used by the event thread
+struct Client {
+    lib: &'static Lib,
+    ctx: *mut mpv_handle,
+}
+
+// SAFETY: the client is only used by one thread at a time
+unsafe impl Send for Client {}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        unsafe { (self.lib.mpv_destroy)(self.ctx) };
+    }
+}
+
+/// Creates a client of the mpv `core` and starts sending its events to `sender`
+pub fn spawn_forwarder(core: *mut mpv_handle, sender: mpsc::Sender<Event>) -> Result<()> {
+    let lib = ffi::lib()?;
+    let ctx = unsafe { (lib.mpv_create_client)(core, c"tori_events".as_ptr()) };
+    if ctx.is_null() {
+        return Err("Failed to create an mpv client to listen to events".into());
+    }
+    let client = Client { lib, ctx };
+
+    for (id, name, format) in OBSERVED {
+        let err = unsafe { (lib.mpv_observe_property)(ctx, id, name.as_ptr(), format) };
+        if err < 0 {
+            return Err(format!(
+                "Failed to observe mpv's {:?}: {}",
+                name,
+                error_string(lib, err)
+            )
+            .into());
+        }
+    }
+
+    thread::spawn(move || forward(client, sender));
+    Ok(())
+}
+
+/// Waits for the events of the `client` until mpv shuts down or the app quits
+fn forward(client: Client, sender: mpsc::Sender<Event>) {
+    loop {
+        // SAFETY: the event is valid until the next mpv_wait_event call on this client
+        let event = unsafe { &*(client.lib.mpv_wait_event)(client.ctx, -1.0) };
+        let event = match event.event_id {
+            MPV_EVENT_SHUTDOWN => return,
+            MPV_EVENT_PLAYBACK_RESTART => Event::Seeked,
+            MPV_EVENT_END_FILE => end_file_event(client.lib, event.data),
+            MPV_EVENT_PROPERTY_CHANGE => match property_event(event) {
+                Some(event) => event,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        // The app has quit
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn property_event(event: &mpv_event) -> Option<Event> {
+    match event.reply_userdata {
+        PAUSE => {
+            // SAFETY: `pause` is observed as MPV_FORMAT_FLAG, which is an int
+            let property = unsafe { &*(event.data as *const mpv_event_property) };
+            if property.format != MPV_FORMAT_FLAG || property.data.is_null() {
+                return None;
+            }
+            let paused = unsafe { *(property.data as *const i32) } != 0;
+            Some(Event::PauseChanged(paused))
+        }
+        MEDIA_TITLE | PLAYLIST_POS => Some(Event::TrackChanged),
+        VOLUME | MUTE => Some(Event::VolumeChanged),
+        // Moves don't change the count, but they're only made by the queue screen, which updates
+        // itself
+        PLAYLIST_COUNT => Some(Event::QueueChanged),
+        _ => None,
+    }
+}
+
+fn end_file_event(lib: &Lib, data: *mut c_void) -> Event {
+    // SAFETY: the data of MPV_EVENT_END_FILE is an mpv_event_end_file
+    let end_file = unsafe { &*(data as *const mpv_event_end_file) };
+    if end_file.reason == MPV_END_FILE_REASON_ERROR {
+        Event::PlayerError(format!(
+            "mpv failed to play the file: {}",
+            error_string(lib, end_file.error)
+        ))
+    } else {
+        Event::EndOfFile
+    }
+}
+
+fn error_string(lib: &Lib, err: i32) -> String {
+    unsafe { CStr::from_ptr((lib.mpv_error_string)(err)) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,124 @@
+//! The parts of mpv's client API used by tori.
+//!
+//! libmpv is loaded when the mpv backend starts instead of being linked, so that tori still runs
+//! without it and falls back to another backend. Only functions and types that didn't change
+//! between the client API versions 1 (mpv <= v0.34) and 2 (mpv >= v0.35) are used, which makes
+//! them work with either.
+
+// The names come from mpv's C API
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_double, c_int, c_void};
+
+use libloading::Library;
+use once_cell::sync::OnceCell;
+
+use crate::error::Result;
+
+/// An mpv client, only used behind pointers
+#[repr(C)]
+pub struct mpv_handle {
+    _private: [u8; 0],
+}
+
+pub type mpv_format = c_int;
+pub const MPV_FORMAT_NONE: mpv_format = 0;
+pub const MPV_FORMAT_STRING: mpv_format = 1;
+pub const MPV_FORMAT_FLAG: mpv_format = 3;
+pub const MPV_FORMAT_INT64: mpv_format = 4;
+pub const MPV_FORMAT_DOUBLE: mpv_format = 5;
+
+pub type mpv_event_id = c_int;
+pub const MPV_EVENT_SHUTDOWN: mpv_event_id = 1;
+pub const MPV_EVENT_END_FILE: mpv_event_id = 7;
+pub const MPV_EVENT_PLAYBACK_RESTART: mpv_event_id = 21;
+pub const MPV_EVENT_PROPERTY_CHANGE: mpv_event_id = 22;
+
+pub const MPV_END_FILE_REASON_ERROR: c_int = 4;
+
+#[repr(C)]
+pub struct mpv_event {
+    pub event_id: mpv_event_id,
+    pub error: c_int,
+    pub reply_userdata: u64,
+    pub data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct mpv_event_property {
+    pub name: *const c_char,
+    pub format: mpv_format,
+    pub data: *mut c_void,
+}
+
+/// Only the fields that come before the ones added by the client API version 2
+#[repr(C)]
+pub struct mpv_event_end_file {
+    pub reason: c_int,
+    pub error: c_int,
+}
+
+/// Declares the functions of libmpv, which are looked up by their names when it's loaded
+macro_rules! functions {
+    ($( fn $name:ident($( $arg:ty ),*) $( -> $ret:ty )?; )*) => {
+        /// The functions of the loaded libmpv
+        pub struct Lib {
+            $( pub $name: unsafe extern "C" fn($( $arg ),*) $( -> $ret )?, )*
+            _library: Library,
+        }
+
+        impl Lib {
+            /// SAFETY: `library` has to be libmpv, so that the functions have these signatures
+            unsafe fn new(library: Library) -> std::result::Result<Self, libloading::Error> {
+                Ok(Self {
+                    $( $name: *library.get(concat!(stringify!($name), "\0").as_bytes())?, )*
+                    _library: library,
+                })
+            }
+        }
+    };
+}
+
+functions! {
+    fn mpv_create() -> *mut mpv_handle;
+    fn mpv_create_client(*mut mpv_handle, *const c_char) -> *mut mpv_handle;
+    fn mpv_initialize(*mut mpv_handle) -> c_int;
+    fn mpv_destroy(*mut mpv_handle);
+    fn mpv_terminate_destroy(*mut mpv_handle);
+    fn mpv_error_string(c_int) -> *const c_char;
+    fn mpv_free(*mut c_void);
+    fn mpv_command_string(*mut mpv_handle, *const c_char) -> c_int;
+    fn mpv_set_property(*mut mpv_handle, *const c_char, mpv_format, *mut c_void) -> c_int;
+    fn mpv_get_property(*mut mpv_handle, *const c_char, mpv_format, *mut c_void) -> c_int;
+    fn mpv_observe_property(*mut mpv_handle, u64, *const c_char, mpv_format) -> c_int;
+    fn mpv_wait_event(*mut mpv_handle, c_double) -> *mut mpv_event;
+}
+
+/// The file names libmpv is installed with, newest first
+#[cfg(target_os = "windows")]
+const NAMES: &[&str] = &["libmpv-2.dll", "mpv-2.dll", "mpv-1.dll"];
+#[cfg(target_os = "macos")]
+const NAMES: &[&str] = &["libmpv.2.dylib", "libmpv.1.dylib", "libmpv.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const NAMES: &[&str] = &["libmpv.so.2", "libmpv.so.1", "libmpv.so"];
+
+/// Loads libmpv the first time it's called
+pub fn lib() -> Result<&'static Lib> {
+    static LIB: OnceCell<std::result::Result<Lib, String>> = OnceCell::new();
+    LIB.get_or_init(load).as_ref().map_err(|e| e.clone().into())
+}
+
+fn load() -> std::result::Result<Lib, String> {
+    let mut errors = Vec::new();
+    for name in NAMES {
+        // SAFETY: a library with one of these names is libmpv
+        match unsafe { Library::new(name).and_then(|library| Lib::new(library)) } {
+            Ok(lib) => return Ok(lib),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    Err(format!(
+        "Couldn't load libmpv, is mpv installed? ({})",
+        errors.join("; ")
+    ))
+}
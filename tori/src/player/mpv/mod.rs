@@ -1,12 +1,30 @@
+use std::{collections::HashMap, sync::mpsc};
+
 use crate::config::Config;
 use crate::error::Result;
+use crate::events::Event;
+use crate::player::{Chapter, RepeatMode};
 
-mod select;
-use select::Mpv;
+mod client;
+mod events;
+mod ffi;
+use client::Mpv;
 
-#[repr(transparent)]
 pub struct MpvPlayer {
     pub(crate) mpv: Mpv,
+    /// The sections of the playlist entries that are one, by the id of the entry. mpv keeps them
+    /// as per-file options, which can't be read back, and the ids don't change when the entries
+    /// are moved.
+    sections: HashMap<i64, (f64, Option<f64>)>,
+}
+
+impl MpvPlayer {
+    /// Remembers the section of the entry at index `i`
+    fn set_section(&mut self, i: usize, start: f64, end: Option<f64>) -> Result<()> {
+        let id = self.mpv.get_i64(&format!("playlist/{}/id", i))?;
+        self.sections.insert(id, (start, end));
+        Ok(())
+    }
 }
 
 impl super::Player for MpvPlayer {
@@ -14,17 +32,28 @@ impl super::Player for MpvPlayer {
         let mpv = Mpv::with_initializer(|mpv| {
             mpv.set_bool("video", false)?;
             mpv.set_i64("volume", 100)?;
+            mpv.set_bool("audio-pitch-correction", true)?;
             if let Some(ao) = &Config::global().mpv_ao {
                 mpv.set_str("ao", ao.as_str())?;
             }
+            if let Some(device) = &Config::global().output_device {
+                mpv.set_str("audio-device", device.as_str())?;
+            }
+            if let Some(replaygain) = &Config::global().replaygain {
+                mpv.set_str("replaygain", replaygain.as_str())?;
+            }
             Ok(())
         })?;
 
-        Ok(Self { mpv })
+        Ok(Self {
+            mpv,
+            sections: HashMap::new(),
+        })
     }
 
     fn play(&mut self, path: &str) -> Result<()> {
         self.mpv.play(path)?;
+        self.sections.clear();
         Ok(())
     }
 
@@ -33,6 +62,50 @@ impl super::Player for MpvPlayer {
         Ok(())
     }
 
+    fn queue_next(&mut self, path: &str) -> Result<()> {
+        // `loadfile <path> insert-next` needs a newer mpv than the versions supported by tori, so
+        // the song is appended and then moved
+        let count = self.playlist_count()?;
+        self.mpv.queue(path)?;
+        match self.playlist_position() {
+            Ok(i) if i < count.saturating_sub(1) => self.playlist_move(count, i + 1),
+            _ => Ok(()),
+        }
+    }
+
+    /// The start is a per-file option in mpv, so the song starts there again if it's played later
+    fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()> {
+        self.mpv
+            .queue_with_options(path, &format!("start={}", seconds))?;
+        Ok(())
+    }
+
+    fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.mpv
+            .play_with_options(path, &section_options(start, end))?;
+        self.sections.clear();
+        self.set_section(0, start, end)
+    }
+
+    fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        self.mpv
+            .queue_with_options(path, &section_options(start, end))?;
+        self.set_section(self.playlist_count()? - 1, start, end)
+    }
+
+    /// Like [queue_at](Self::queue_at), the song starts at `seconds` again if it's played later
+    fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()> {
+        self.mpv
+            .queue_with_options(path, &section_options(seconds, end))?;
+        self.set_section(self.playlist_count()? - 1, start, end)
+    }
+
     fn seek(&mut self, seconds: f64) -> Result<()> {
         if seconds >= 0.0 {
             self.mpv.seek_forward(seconds)?
@@ -65,23 +138,30 @@ impl super::Player for MpvPlayer {
         Ok(())
     }
 
-    fn toggle_loop_file(&mut self) -> Result<()> {
-        let status = self.mpv.get_str("loop-file");
-        let next_status = match status.as_deref() {
-            Ok("no") => "inf",
-            _ => "no",
+    fn set_repeat(&mut self, mode: RepeatMode) -> Result<()> {
+        let (loop_file, loop_playlist) = match mode {
+            RepeatMode::Off => ("no", "no"),
+            RepeatMode::One => ("inf", "no"),
+            RepeatMode::All => ("no", "inf"),
         };
-        self.mpv.set_str("loop-file", next_status)?;
+        self.mpv.set_str("loop-file", loop_file)?;
+        self.mpv.set_str("loop-playlist", loop_playlist)?;
         Ok(())
     }
 
-    fn looping_file(&self) -> Result<bool> {
-        let status = self.mpv.get_str("loop-file")?;
-        Ok(status == "inf")
+    /// The loop options can also be set to a number of times, which counts as repeating too
+    fn repeat_mode(&self) -> Result<RepeatMode> {
+        if self.mpv.get_str("loop-file")? != "no" {
+            Ok(RepeatMode::One)
+        } else if self.mpv.get_str("loop-playlist")? != "no" {
+            Ok(RepeatMode::All)
+        } else {
+            Ok(RepeatMode::Off)
+        }
     }
 
     fn volume(&self) -> Result<i64> {
-        Ok(self.mpv.get_i64("volume")?)
+        self.mpv.get_i64("volume")
     }
 
     fn add_volume(&mut self, x: isize) -> Result<()> {
@@ -100,31 +180,40 @@ impl super::Player for MpvPlayer {
     }
 
     fn muted(&self) -> Result<bool> {
-        Ok(self.mpv.get_bool("mute")?)
+        self.mpv.get_bool("mute")
+    }
+
+    fn speed(&self) -> Result<f64> {
+        self.mpv.get_f64("speed")
+    }
+
+    fn set_speed(&mut self, speed: f64) -> Result<()> {
+        self.mpv.set_f64("speed", speed)?;
+        Ok(())
     }
 
     fn media_title(&self) -> Result<String> {
-        Ok(self.mpv.get_str("media-title")?)
+        self.mpv.get_str("media-title")
     }
 
     fn percent_pos(&self) -> Result<i64> {
-        Ok(self.mpv.get_i64("percent-pos")?)
+        self.mpv.get_i64("percent-pos")
     }
 
     fn time_pos(&self) -> Result<i64> {
-        Ok(self.mpv.get_i64("time-pos")?)
+        self.mpv.get_i64("time-pos")
     }
 
-    fn time_remaining(&self) -> Result<i64> {
-        Ok(self.mpv.get_i64("time-remaining")?)
+    fn precise_time_pos(&self) -> Result<f64> {
+        self.mpv.get_f64("time-pos")
     }
 
-    fn paused(&self) -> Result<bool> {
-        Ok(self.mpv.get_bool("pause")?)
+    fn time_remaining(&self) -> Result<i64> {
+        self.mpv.get_i64("time-remaining")
     }
 
-    fn shuffle(&mut self) -> Result<()> {
-        Ok(self.mpv.command("playlist-shuffle", &[])?)
+    fn paused(&self) -> Result<bool> {
+        self.mpv.get_bool("pause")
     }
 
     fn playlist_count(&self) -> Result<usize> {
@@ -132,13 +221,120 @@ impl super::Player for MpvPlayer {
     }
 
     fn playlist_track_title(&self, i: usize) -> Result<String> {
-        Ok(self
-            .mpv
+        self.mpv
             .get_str(&format!("playlist/{}/title", i))
-            .or_else(|_| self.mpv.get_str(&format!("playlist/{}/filename", i)))?)
+            .or_else(|_| self.mpv.get_str(&format!("playlist/{}/filename", i)))
+    }
+
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        self.mpv.get_str(&format!("playlist/{}/filename", i))
+    }
+
+    fn playlist_track_section(&self, i: usize) -> Result<Option<(f64, Option<f64>)>> {
+        let id = self.mpv.get_i64(&format!("playlist/{}/id", i))?;
+        Ok(self.sections.get(&id).copied())
     }
 
     fn playlist_position(&self) -> Result<usize> {
         Ok(self.mpv.get_i64("playlist-playing-pos")? as usize)
     }
+
+    fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        // mpv moves the entry to the place of the entry at its second argument, so moving it down
+        // means taking the place of the one after `to`
+        let target = if from < to { to + 1 } else { to };
+        self.mpv
+            .command("playlist-move", &[&from.to_string(), &target.to_string()])?;
+        Ok(())
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        self.mpv.command("playlist-remove", &[&i.to_string()])?;
+        Ok(())
+    }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        self.mpv.command("playlist-play-index", &[&i.to_string()])?;
+        Ok(())
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        self.mpv.command("playlist-clear", &[])?;
+        Ok(())
+    }
+
+    fn chapters(&self) -> Result<Vec<Chapter>> {
+        // chapter-list is unavailable while nothing is playing
+        let count = self.mpv.get_i64("chapter-list/count").unwrap_or(0);
+        (0..count)
+            .map(|i| {
+                Ok(Chapter {
+                    title: self
+                        .mpv
+                        .get_str(&format!("chapter-list/{}/title", i))
+                        .unwrap_or_else(|_| format!("Chapter {}", i + 1)),
+                    time: self.mpv.get_f64(&format!("chapter-list/{}/time", i))?,
+                })
+            })
+            .collect()
+    }
+
+    /// mpv reports -1 before the first chapter starts
+    fn chapter(&self) -> Result<Option<usize>> {
+        Ok(self
+            .mpv
+            .get_i64("chapter")
+            .ok()
+            .and_then(|i| usize::try_from(i).ok()))
+    }
+
+    fn set_chapter(&mut self, i: usize) -> Result<()> {
+        self.mpv.set_i64("chapter", i as i64)?;
+        Ok(())
+    }
+
+    /// The points are "no" when they're not set
+    fn ab_loop(&self) -> Result<(Option<f64>, Option<f64>)> {
+        let point =
+            |name: &str| -> Result<Option<f64>> { Ok(self.mpv.get_str(name)?.parse().ok()) };
+        Ok((point("ab-loop-a")?, point("ab-loop-b")?))
+    }
+
+    fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()> {
+        for (name, point) in [("ab-loop-a", a), ("ab-loop-b", b)] {
+            match point {
+                Some(seconds) => self.mpv.set_f64(name, seconds)?,
+                None => self.mpv.set_str(name, "no")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>> {
+        let count = self.mpv.get_i64("audio-device-list/count")?;
+        (0..count)
+            .map(|i| self.mpv.get_str(&format!("audio-device-list/{}/name", i)))
+            .collect()
+    }
+
+    fn output_device(&self) -> Result<String> {
+        self.mpv.get_str("audio-device")
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        self.mpv.set_str("audio-device", device)?;
+        Ok(())
+    }
+
+    fn forward_events(&mut self, sender: mpsc::Sender<Event>) -> Result<()> {
+        events::spawn_forwarder(self.mpv.handle(), sender)
+    }
+}
+
+/// The per-file options that play a file from `start` to `end`
+fn section_options(start: f64, end: Option<f64>) -> String {
+    match end {
+        Some(end) => format!("start={},end={}", start, end),
+        None => format!("start={}", start),
+    }
 }
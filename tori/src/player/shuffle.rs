@@ -0,0 +1,335 @@
+//! Shuffles the queue of the player without forgetting its original order, so it can be
+//! unshuffled later. The songs are only moved around with [Player::playlist_move], so the current
+//! song keeps playing.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    path::Path,
+};
+
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use super::Player;
+use crate::error::Result;
+
+/// How many of the last played songs the weighted shuffle tries to avoid
+const HISTORY_LEN: usize = 50;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShuffleMode {
+    /// The queue is in the order the songs were added
+    #[default]
+    Off,
+    /// Every song in a random order
+    Songs,
+    /// The albums in a random order, each one with its songs in order. Songs are in the same
+    /// album when they're in the same directory.
+    Albums,
+    /// Every song in a random order, but the recently played ones tend to come last
+    Weighted,
+}
+
+impl ShuffleMode {
+    /// The mode that comes after this one when cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Songs,
+            Self::Songs => Self::Albums,
+            Self::Albums => Self::Weighted,
+            Self::Weighted => Self::Off,
+        }
+    }
+}
+
+impl fmt::Display for ShuffleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "not shuffled"),
+            Self::Songs => write!(f, "shuffled"),
+            Self::Albums => write!(f, "shuffled by album"),
+            Self::Weighted => write!(f, "weighted shuffle"),
+        }
+    }
+}
+
+/// The shuffle mode of the queue, and what's needed to undo it
+#[derive(Debug, Default)]
+pub struct Shuffle {
+    mode: ShuffleMode,
+    /// Paths of the songs in the queue before it was shuffled
+    original: Vec<String>,
+    /// Paths of the last played songs, the most recent one last
+    history: VecDeque<String>,
+}
+
+impl Shuffle {
+    pub fn mode(&self) -> ShuffleMode {
+        self.mode
+    }
+
+    /// Paths of the songs in the queue before it was shuffled. Empty when it isn't shuffled.
+    pub fn original(&self) -> &[String] {
+        &self.original
+    }
+
+    /// Takes the mode and the original order of a queue that's already shuffled, like one restored
+    /// from a session, without reordering it
+    pub fn restore(&mut self, mode: ShuffleMode, original: Vec<String>) {
+        self.original = match mode {
+            ShuffleMode::Off => Vec::new(),
+            _ => original,
+        };
+        self.mode = mode;
+    }
+
+    /// Remembers that the song at `path` was played, for the weighted shuffle
+    pub fn song_played(&mut self, path: String) {
+        if self.history.back() == Some(&path) {
+            return;
+        }
+        self.history.push_back(path);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+    }
+
+    /// Reorders the queue of the `player`. Shuffling always starts from the original order, and
+    /// the current song is moved to the top of the queue. With [ShuffleMode::Off], the original
+    /// order is restored, and the songs that were queued while shuffled come after the others.
+    pub fn set_mode(&mut self, player: &mut impl Player, mode: ShuffleMode) -> Result<()> {
+        let queue = (0..player.playlist_count()?)
+            .map(|i| player.playlist_track_path(i))
+            .collect::<Result<Vec<_>>>()?;
+        let current = player.playlist_position().ok();
+
+        let base = unshuffled_order(&queue, &self.original);
+        let order = if mode == ShuffleMode::Off {
+            base
+        } else {
+            let paths: Vec<&str> = base.iter().map(|&i| queue[i].as_str()).collect();
+            let current = current.and_then(|c| base.iter().position(|&i| i == c));
+            let history: Vec<&str> = self.history.iter().map(String::as_str).collect();
+            shuffled_order(&paths, current, mode, &history, &mut rand::thread_rng())
+                .into_iter()
+                .map(|i| base[i])
+                .collect()
+        };
+        reorder(player, &order)?;
+
+        if mode == ShuffleMode::Off {
+            self.original.clear();
+        } else if self.mode == ShuffleMode::Off {
+            self.original = queue;
+        }
+        self.mode = mode;
+        Ok(())
+    }
+}
+
+/// Moves the songs of the queue so the song that was `order[i]`-th becomes the `i`-th one
+fn reorder(player: &mut impl Player, order: &[usize]) -> Result<()> {
+    // The original index of the songs, in the order they're in the queue while they're moved
+    let mut queue: Vec<usize> = (0..order.len()).collect();
+    for (to, song) in order.iter().enumerate() {
+        let from = queue.iter().position(|x| x == song).unwrap();
+        if from != to {
+            player.playlist_move(from, to)?;
+            queue.remove(from);
+            queue.insert(to, *song);
+        }
+    }
+    Ok(())
+}
+
+/// The order that puts the songs of the `queue` back in the `original` order. Songs that aren't in
+/// the original queue keep their relative order, after the others.
+fn unshuffled_order(queue: &[String], original: &[String]) -> Vec<usize> {
+    // The same song may be queued more than once
+    let mut positions: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (i, path) in queue.iter().enumerate() {
+        positions.entry(path).or_default().push_back(i);
+    }
+
+    let mut order: Vec<usize> = original
+        .iter()
+        .filter_map(|path| positions.get_mut(path.as_str())?.pop_front())
+        .collect();
+
+    let mut ordered = vec![false; queue.len()];
+    for &i in &order {
+        ordered[i] = true;
+    }
+    order.extend((0..queue.len()).filter(|&i| !ordered[i]));
+    order
+}
+
+/// A random order of the songs at `paths`, starting with the `current` song.
+/// `history` has the paths of the last played songs, the most recent one last.
+fn shuffled_order(
+    paths: &[&str],
+    current: Option<usize>,
+    mode: ShuffleMode,
+    history: &[&str],
+    rng: &mut impl Rng,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = match mode {
+        ShuffleMode::Off => (0..paths.len()).collect(),
+        ShuffleMode::Songs => {
+            let mut order: Vec<usize> = (0..paths.len()).collect();
+            order.shuffle(rng);
+            order
+        }
+        ShuffleMode::Albums => {
+            let mut albums: Vec<Vec<usize>> = Vec::new();
+            let mut album_index: HashMap<&str, usize> = HashMap::new();
+            for (i, path) in paths.iter().enumerate() {
+                let album = *album_index.entry(album_of(path)).or_insert_with(|| {
+                    albums.push(Vec::new());
+                    albums.len() - 1
+                });
+                albums[album].push(i);
+            }
+            albums.shuffle(rng);
+
+            // The album of the current song comes first, starting from the current song
+            if let Some(current) = current {
+                if let Some(i) = albums.iter().position(|album| album.contains(&current)) {
+                    let mut album = albums.remove(i);
+                    let start = album.iter().position(|&song| song == current).unwrap();
+                    album.rotate_left(start);
+                    albums.insert(0, album);
+                }
+            }
+            albums.concat()
+        }
+        ShuffleMode::Weighted => {
+            // Weighted random sampling, by Efraimidis and Spirakis: sorting by u^(1/weight), where
+            // u is uniformly random, puts heavier songs first more often
+            let mut keys: Vec<(f64, usize)> = paths
+                .iter()
+                .enumerate()
+                .map(|(i, path)| {
+                    let u: f64 = rng.gen();
+                    (u.powf(1.0 / weight(path, history)), i)
+                })
+                .collect();
+            keys.sort_by(|a, b| b.0.total_cmp(&a.0));
+            keys.into_iter().map(|(_, i)| i).collect()
+        }
+    };
+
+    if let Some(current) = current {
+        if let Some(i) = order.iter().position(|&song| song == current) {
+            order.remove(i);
+            order.insert(0, current);
+        }
+    }
+    order
+}
+
+/// Songs in the same directory are considered to be in the same album. Every URL is an album of
+/// its own.
+fn album_of(path: &str) -> &str {
+    if path.contains("://") {
+        return path;
+    }
+    Path::new(path)
+        .parent()
+        .and_then(Path::to_str)
+        .unwrap_or(path)
+}
+
+/// Songs that weren't played recently weigh 1. The others weigh less the more recently they were
+/// played, down to 1 / (HISTORY_LEN + 1) for the last played song.
+fn weight(path: &str, history: &[&str]) -> f64 {
+    match history.iter().rposition(|&p| p == path) {
+        Some(i) => (history.len() - i) as f64 / (HISTORY_LEN + 1) as f64,
+        None => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const PATHS: [&str; 7] = [
+        "/music/a/1.mp3",
+        "/music/a/2.mp3",
+        "/music/a/3.mp3",
+        "/music/b/1.mp3",
+        "/music/b/2.mp3",
+        "/music/c/1.mp3",
+        "https://youtu.be/x",
+    ];
+
+    fn is_permutation(order: &[usize], len: usize) -> bool {
+        let mut sorted = order.to_vec();
+        sorted.sort();
+        sorted == (0..len).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn test_shuffled_order() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for mode in [
+            ShuffleMode::Off,
+            ShuffleMode::Songs,
+            ShuffleMode::Albums,
+            ShuffleMode::Weighted,
+        ] {
+            for current in [None, Some(0), Some(4)] {
+                let order = shuffled_order(&PATHS, current, mode, &[], &mut rng);
+                assert!(is_permutation(&order, PATHS.len()), "{:?}", order);
+                if let Some(current) = current {
+                    assert_eq!(order[0], current);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shuffle_by_album() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let order = shuffled_order(&PATHS, Some(1), ShuffleMode::Albums, &[], &mut rng);
+            assert_eq!(order[..3], [1, 2, 0]);
+
+            // The songs of each album are together and in order
+            let b = order.iter().position(|&i| i == 3).unwrap();
+            assert_eq!(order[b + 1], 4);
+        }
+    }
+
+    #[test]
+    fn test_weighted_shuffle_avoids_recent_songs() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let history = [PATHS[2], PATHS[5]];
+        let mut positions = [0; PATHS.len()];
+        for _ in 0..1000 {
+            let order = shuffled_order(&PATHS, None, ShuffleMode::Weighted, &history, &mut rng);
+            for (position, &song) in order.iter().enumerate() {
+                positions[song] += position;
+            }
+        }
+        let average = |song: usize| positions[song] as f64 / 1000.0;
+
+        // Without weights, every song would be at position 3 on average
+        assert!(average(5) > 5.0);
+        assert!(average(2) > 4.5);
+        assert!(average(5) > average(2));
+        assert!(average(0) < 3.0);
+    }
+
+    #[test]
+    fn test_unshuffled_order() {
+        let original: Vec<String> = ["a", "b", "a", "c"].map(String::from).to_vec();
+        // "c" was removed, and "d" was queued after shuffling
+        let queue: Vec<String> = ["b", "a", "d", "a"].map(String::from).to_vec();
+        assert_eq!(unshuffled_order(&queue, &original), [1, 0, 3, 2]);
+        assert_eq!(unshuffled_order(&queue, &[]), [0, 1, 2, 3]);
+    }
+}
@@ -1,106 +1,301 @@
-use tori_player::{Result, controller::Controller};
+use std::{sync::mpsc, thread};
 
-macro_rules! my_todo {
-    () => {
-        Ok(Default::default())
-    };
-}
+use super::{Chapter, RepeatMode};
+use crate::{config::Config, error::Result, events::Event};
+use tori_player::{
+    controller::{self, Controller},
+    OutputDevice, ReplayGainMode,
+};
 
 impl super::Player for tori_player::Player {
     fn new() -> Result<Self> {
-        let controller = Controller::default();
+        let mut controller = Controller::default();
+        if let Some(device) = &Config::global().output_device {
+            controller.set_output_device(OutputDevice::parse(device))?;
+        }
+        if let Some(replaygain) = &Config::global().replaygain {
+            let mode = ReplayGainMode::parse(replaygain)
+                .ok_or_else(|| format!("Unknown replaygain mode: {}", replaygain))?;
+            controller.set_replaygain(mode)?;
+        }
         Ok(Self { controller })
     }
 
     fn play(&mut self, path: &str) -> Result<()> {
-        self.controller.play(path)
+        Ok(self.controller.play(path)?)
     }
 
     fn queue(&mut self, path: &str) -> Result<()> {
-        my_todo!()
+        Ok(self.controller.queue(path)?)
+    }
+
+    fn queue_next(&mut self, path: &str) -> Result<()> {
+        Ok(self.controller.queue_next(path)?)
+    }
+
+    fn queue_at(&mut self, path: &str, seconds: f64) -> Result<()> {
+        Ok(self.controller.queue_at(path, seconds)?)
+    }
+
+    fn play_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        Ok(self.controller.play_section(path, start, end)?)
+    }
+
+    fn queue_section(&mut self, path: &str, start: f64, end: Option<f64>) -> Result<()> {
+        Ok(self.controller.queue_section(path, start, end)?)
+    }
+
+    fn queue_section_at(
+        &mut self,
+        path: &str,
+        start: f64,
+        end: Option<f64>,
+        seconds: f64,
+    ) -> Result<()> {
+        Ok(self
+            .controller
+            .queue_section_at(path, start, end, seconds)?)
     }
 
     fn seek(&mut self, seconds: f64) -> Result<()> {
-        my_todo!()
+        match current_section(&self.controller) {
+            // Seeking past the end of a section would play the next track of the file
+            Some((start, end)) => {
+                let end = end.unwrap_or(f64::INFINITY);
+                let time = (self.controller.time_pos() + seconds).clamp(start, end);
+                Ok(self.controller.seek_absolute(time)?)
+            }
+            None => Ok(self.controller.seek(seconds)?),
+        }
     }
 
     fn seek_absolute(&mut self, percent: usize) -> Result<()> {
-        my_todo!()
+        let (start, end) =
+            bounds(&self.controller).ok_or("Can't seek: the duration of the song is unknown")?;
+        let percent = percent.min(100) as f64;
+        Ok(self
+            .controller
+            .seek_absolute(start + (end - start) * percent / 100.0)?)
     }
 
     fn playlist_next(&mut self) -> Result<()> {
-        my_todo!()
+        Ok(self.controller.playlist_next()?)
     }
 
     fn playlist_previous(&mut self) -> Result<()> {
-        my_todo!()
+        Ok(self.controller.playlist_previous()?)
     }
 
     fn toggle_pause(&mut self) -> Result<()> {
-        my_todo!()
+        Ok(self.controller.toggle_pause()?)
     }
 
-    fn toggle_loop_file(&mut self) -> Result<()> {
-        my_todo!()
+    fn set_repeat(&mut self, mode: RepeatMode) -> Result<()> {
+        let mode = match mode {
+            RepeatMode::Off => controller::RepeatMode::Off,
+            RepeatMode::One => controller::RepeatMode::One,
+            RepeatMode::All => controller::RepeatMode::All,
+        };
+        Ok(self.controller.set_repeat(mode)?)
     }
 
-    fn looping_file(&self) -> Result<bool> {
-        my_todo!()
+    fn repeat_mode(&self) -> Result<RepeatMode> {
+        Ok(match self.controller.repeat() {
+            controller::RepeatMode::Off => RepeatMode::Off,
+            controller::RepeatMode::One => RepeatMode::One,
+            controller::RepeatMode::All => RepeatMode::All,
+        })
     }
 
     fn volume(&self) -> Result<i64> {
-        my_todo!()
+        Ok(self.controller.volume())
     }
 
     fn add_volume(&mut self, x: isize) -> Result<()> {
-        my_todo!()
+        let volume = self.controller.volume() + x as i64;
+        Ok(self.controller.set_volume(volume)?)
     }
 
     fn set_volume(&mut self, x: i64) -> Result<()> {
-        my_todo!()
+        Ok(self.controller.set_volume(x)?)
+    }
+
+    /// tori-player has no time stretching, so it always plays at the normal speed
+    fn speed(&self) -> Result<f64> {
+        Ok(1.0)
+    }
+
+    fn set_speed(&mut self, speed: f64) -> Result<()> {
+        if speed != 1.0 {
+            return Err("tori-player can't change the playback speed yet".into());
+        }
+        Ok(())
     }
 
     fn toggle_mute(&mut self) -> Result<()> {
-        my_todo!()
+        let muted = self.controller.muted();
+        Ok(self.controller.set_muted(!muted)?)
     }
 
     fn muted(&self) -> Result<bool> {
-        my_todo!()
+        Ok(self.controller.muted())
     }
 
     fn media_title(&self) -> Result<String> {
-        my_todo!()
+        Ok(self.controller.media_title().ok_or("Nothing is playing")?)
     }
 
     fn percent_pos(&self) -> Result<i64> {
-        my_todo!()
+        let (start, end) = bounds(&self.controller).ok_or("Unknown duration")?;
+        Ok((100.0 * (self.controller.time_pos() - start) / (end - start)) as i64)
     }
 
     fn time_pos(&self) -> Result<i64> {
-        my_todo!()
+        Ok(self.controller.time_pos() as i64)
     }
 
-    fn time_remaining(&self) -> Result<i64> {
-        my_todo!()
+    fn precise_time_pos(&self) -> Result<f64> {
+        Ok(self.controller.time_pos())
     }
 
-    fn paused(&self) -> Result<bool> {
-        my_todo!()
+    fn time_remaining(&self) -> Result<i64> {
+        let (_, end) = bounds(&self.controller).ok_or("Unknown duration")?;
+        Ok((end - self.controller.time_pos()).max(0.0) as i64)
     }
 
-    fn shuffle(&mut self) -> Result<()> {
-        my_todo!()
+    fn paused(&self) -> Result<bool> {
+        Ok(self.controller.paused())
     }
 
     fn playlist_count(&self) -> Result<usize> {
-        my_todo!()
+        Ok(self.controller.playlist_len())
     }
 
     fn playlist_track_title(&self, i: usize) -> Result<String> {
-        my_todo!()
+        Ok(self
+            .controller
+            .playlist_title(i)
+            .ok_or("No song at this position of the playlist")?)
+    }
+
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        Ok(self
+            .controller
+            .playlist_path(i)
+            .ok_or("No song at this position of the playlist")?)
+    }
+
+    fn playlist_track_section(&self, i: usize) -> Result<Option<(f64, Option<f64>)>> {
+        if i >= self.controller.playlist_len() {
+            return Err("No song at this position of the playlist".into());
+        }
+        Ok(self.controller.playlist_section(i))
     }
 
     fn playlist_position(&self) -> Result<usize> {
-        my_todo!()
+        Ok(self
+            .controller
+            .playlist_position()
+            .ok_or("Nothing is playing")?)
+    }
+
+    fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        Ok(self.controller.playlist_move(from, to)?)
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        Ok(self.controller.playlist_remove(i)?)
     }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        Ok(self.controller.play_index(i)?)
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        self.controller.playlist_clear();
+        Ok(())
+    }
+
+    fn chapters(&self) -> Result<Vec<Chapter>> {
+        Ok(self
+            .controller
+            .chapters()
+            .into_iter()
+            .map(|chapter| Chapter {
+                title: chapter.title,
+                time: chapter.time,
+            })
+            .collect())
+    }
+
+    fn chapter(&self) -> Result<Option<usize>> {
+        Ok(self.controller.chapter())
+    }
+
+    fn set_chapter(&mut self, i: usize) -> Result<()> {
+        Ok(self.controller.set_chapter(i)?)
+    }
+
+    fn ab_loop(&self) -> Result<(Option<f64>, Option<f64>)> {
+        Ok(self.controller.ab_loop())
+    }
+
+    fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> Result<()> {
+        Ok(self.controller.set_ab_loop(a, b)?)
+    }
+
+    fn output_devices(&self) -> Result<Vec<String>> {
+        Ok(tori_player::list_devices()
+            .iter()
+            .map(ToString::to_string)
+            .collect())
+    }
+
+    fn output_device(&self) -> Result<String> {
+        Ok(self.controller.output_device().to_string())
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        Ok(self
+            .controller
+            .set_output_device(OutputDevice::parse(device))?)
+    }
+
+    fn forward_events(&mut self, sender: mpsc::Sender<Event>) -> Result<()> {
+        let events = self.controller.events();
+        thread::spawn(move || {
+            for event in events {
+                let event = match event {
+                    controller::Event::TrackChanged => Event::TrackChanged,
+                    controller::Event::PauseChanged(paused) => Event::PauseChanged(paused),
+                    controller::Event::Seeked => Event::Seeked,
+                    controller::Event::EndOfFile => Event::EndOfFile,
+                    controller::Event::VolumeChanged => Event::VolumeChanged,
+                    controller::Event::PlaylistChanged => Event::QueueChanged,
+                    controller::Event::Error(e) => Event::PlayerError(e.to_string()),
+                };
+                // The app has quit
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// The section of the file the current entry plays, if it's only a part of it, like the tracks of a
+/// CUE sheet
+fn current_section(controller: &Controller) -> Option<(f64, Option<f64>)> {
+    controller
+        .playlist_position()
+        .and_then(|i| controller.playlist_section(i))
+}
+
+/// Where the current entry starts and ends in its file, in seconds: its section, or else the whole
+/// file. None if the end isn't known.
+fn bounds(controller: &Controller) -> Option<(f64, f64)> {
+    let (start, end) = current_section(controller).unwrap_or((0.0, None));
+    let end = end.or(controller.duration())?;
+    Some((start, end)).filter(|(start, end)| end > start)
 }
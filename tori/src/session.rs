@@ -0,0 +1,205 @@
+//! What tori was doing when it was closed: the queue of the player, its playback state, how the
+//! queue is shuffled and the song selected in the browse screen. The session is saved on quit,
+//! and restored on startup with the `restore_session` option.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    player::{shuffle::ShuffleMode, Player, RepeatMode},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedSong {
+    pub path: String,
+    pub title: String,
+    /// Where the song starts and ends in its file, in seconds, for songs that are a section of it
+    /// like the tracks of a CUE sheet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub queue: Vec<QueuedSong>,
+    /// Index of the song being played in the queue
+    pub position: Option<usize>,
+    /// Playback position of the current song, in seconds
+    pub time_pos: i64,
+    pub volume: i64,
+    pub muted: bool,
+    pub repeat: RepeatMode,
+    #[serde(default)]
+    pub shuffle: ShuffleMode,
+    /// Paths of the songs in the queue before it was shuffled, so it can be unshuffled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unshuffled: Vec<String>,
+    /// Name of the playlist selected in the browse screen
+    pub playlist: Option<String>,
+    /// Index of the selected song in that playlist
+    pub song: Option<usize>,
+}
+
+impl Session {
+    /// $DATA_DIR/tori/session.yaml
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_default()
+            .join("tori")
+            .join("session.yaml")
+    }
+
+    /// Reads the queue and the playback state of the `player`. The shuffle state and the selection
+    /// of the browse screen are left empty.
+    pub fn from_player(player: &impl Player) -> Result<Self> {
+        let queue: Vec<QueuedSong> = (0..player.playlist_count()?)
+            .map(|i| {
+                let section = player.playlist_track_section(i)?;
+                Ok(QueuedSong {
+                    path: player.playlist_track_path(i)?,
+                    title: player.playlist_track_title(i)?,
+                    start: section.map(|(start, _)| start),
+                    end: section.and_then(|(_, end)| end),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        // mpv reports -1 when nothing is playing
+        let position = player.playlist_position().ok().filter(|&i| i < queue.len());
+
+        Ok(Self {
+            time_pos: position.and_then(|_| player.time_pos().ok()).unwrap_or(0),
+            queue,
+            position,
+            volume: player.volume()?,
+            muted: player.muted()?,
+            repeat: player.repeat_mode()?,
+            shuffle: ShuffleMode::Off,
+            unshuffled: Vec::new(),
+            playlist: None,
+            song: None,
+        })
+    }
+
+    /// Loads the session saved at `path`, if there's one
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        match fs::File::open(path) {
+            Ok(file) => serde_yaml::from_reader(file).map(Some).map_err(|e| {
+                format!("Couldn't parse the session file {}: {}", path.display(), e).into()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Queues the songs in the `player` and restores its state. The current song is paused at the
+    /// position it was at.
+    pub fn restore(&self, player: &mut impl Player) -> Result<()> {
+        if self.position.is_some() && !player.paused()? {
+            player.toggle_pause()?;
+        }
+
+        for (i, song) in self.queue.iter().enumerate() {
+            let section = match (song.start, song.end) {
+                (None, None) => None,
+                (start, end) => Some((start.unwrap_or(0.0), end)),
+            };
+            match (section, Some(i) == self.position) {
+                (Some((start, end)), true) => {
+                    player.queue_section_at(&song.path, start, end, self.time_pos as f64)?
+                }
+                (Some((start, end)), false) => player.queue_section(&song.path, start, end)?,
+                (None, true) => player.queue_at(&song.path, self.time_pos as f64)?,
+                (None, false) => player.queue(&song.path)?,
+            }
+        }
+        if let Some(i) = self.position {
+            player.playlist_play_index(i)?;
+        }
+
+        player.set_volume(self.volume)?;
+        if player.muted()? != self.muted {
+            player.toggle_mute()?;
+        }
+        player.set_repeat(self.repeat)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::mock::{Call, MockPlayer};
+
+    fn player() -> MockPlayer {
+        MockPlayer {
+            playlist: ["/music/a.mp3", "/music/b.mp3", "/music/c.mp3"]
+                .map(String::from)
+                .to_vec(),
+            position: Some(1),
+            volume: 60,
+            muted: true,
+            repeat: RepeatMode::All,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut session = Session::from_player(&player()).unwrap();
+        session.playlist = Some("rock".into());
+        session.song = Some(2);
+        assert_eq!(session.queue[1].title, "b");
+
+        let path = std::env::temp_dir()
+            .join(format!("tori-session-{}", std::process::id()))
+            .join("session.yaml");
+        assert_eq!(Session::load(&path).unwrap(), None);
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap(), Some(session));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        let session = Session::from_player(&player()).unwrap();
+        let mut restored = MockPlayer::default();
+        session.restore(&mut restored).unwrap();
+
+        assert_eq!(restored.playlist, player().playlist);
+        assert_eq!(restored.position, Some(1));
+        assert!(restored.paused);
+        assert!(restored.muted);
+        assert_eq!(restored.volume, 60);
+        assert_eq!(restored.repeat, RepeatMode::All);
+        assert!(restored
+            .calls
+            .contains(&Call::QueueAt("/music/b.mp3".into(), 0.0)));
+    }
+
+    #[test]
+    fn test_restore_empty_queue() {
+        let session = Session::from_player(&MockPlayer::default()).unwrap();
+        assert_eq!(session.position, None);
+
+        let mut restored = MockPlayer::default();
+        session.restore(&mut restored).unwrap();
+        assert!(restored.playlist.is_empty());
+        assert!(!restored.paused);
+    }
+}